@@ -1,13 +1,38 @@
 use crate::geo;
 use crate::style;
+use crate::cache::DiskCache;
+use crate::error::Error;
+use crate::osm::{DataSource, OsmData};
 
 use std::collections::HashMap;
-use std::io;
-use reqwest;
-use json;
+use std::thread;
+use std::time::{Duration, SystemTime};
 
-static NOMINATIM_API_URL: &str = "https://nominatim.openstreetmap.org/search";
-static OVERPASS_API_URL: &str = "https://overpass.kumi.systems/api/interpreter";
+/// Default Nominatim endpoints, tried in this order
+pub static NOMINATIM_API_URLS: [&str; 1] = ["https://nominatim.openstreetmap.org/search"];
+
+/// Default Overpass endpoints (public mirrors), tried in this order
+pub static OVERPASS_API_URLS: [&str; 3] = [
+    "https://overpass.kumi.systems/api/interpreter",
+    "https://overpass-api.de/api/interpreter",
+    "https://maps.mail.ru/osm/tools/overpass/api/interpreter",
+];
+
+/// Default timeout (in seconds) of the requests to Overpass
+pub const DEFAULT_OVERPASS_TIMEOUT: u32 = 30;
+
+/// Default number of retries of a failed request on each endpoint
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// Default delay (in milliseconds) before the first retry. It is doubled at each new retry, up to MAX_RETRY_DELAY
+pub const DEFAULT_RETRY_DELAY_MS: u64 = 1000;
+
+/// Longest delay waited before a retry. If a server asks to wait longer (with Retry-After),
+/// the next endpoint is tried instead
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+static NOMINATIM_CACHE_NAMESPACE: &str = "nominatim";
+static OVERPASS_CACHE_NAMESPACE: &str = "overpass";
 static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
 
 
@@ -23,7 +48,10 @@ static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_P
 
 /// Struct used to generate the text to pass to Overpass API
 pub struct RequestBuilder {
-    get_building: bool, // If true, the request will ONLY return buildings (the center of their geometry
+    get_building: bool, // If true, the request will ONLY return buildings (their footprint: the way and its nodes)
+    detail_lvl: u8,     // Level of details of the requested ways (see style::get_way_types)
+    layers: Vec<style::Layer>,  // Layers of the requested ways
+    poi_classes: Vec<style::PoiClass>,  // Classes of the requested points of interest
     timeout: u32,
     bounding_box: geo::BoundingBox,
 }
@@ -42,12 +70,16 @@ impl RequestBuilder {
 
 
 
-    /// Create a new RequestBuilder. The bounding_box parameter is the bounding box of the research
+    /// Create a new RequestBuilder. The bounding_box parameter is the bounding box of the research.
+    /// The level of details is deduced from the size of the bounding box, and the ways of every layer are requested.
     pub fn new(bounding_box: geo::BoundingBox, timeout: u32) -> RequestBuilder {
         RequestBuilder {
             get_building: false,
-            bounding_box: bounding_box,
-            timeout: timeout,
+            detail_lvl: RequestBuilder::get_lvl_details(bounding_box.dim_km[0]),
+            layers: style::ALL_LAYERS.to_vec(),
+            poi_classes: Vec::new(),
+            bounding_box,
+            timeout,
         }
     }
 
@@ -60,6 +92,27 @@ impl RequestBuilder {
 
 
 
+    /// Set the level of details of the requested ways.
+    pub fn set_detail_lvl(&mut self, value: u8) {
+        self.detail_lvl = value;
+    }
+
+
+
+    /// Set the layers of the requested ways.
+    pub fn set_layers(&mut self, layers: &[style::Layer]) {
+        self.layers = layers.to_vec();
+    }
+
+
+
+    /// Set the classes of the requested points of interest.
+    pub fn set_poi_classes(&mut self, poi_classes: &[style::PoiClass]) {
+        self.poi_classes = poi_classes.to_vec();
+    }
+
+
+
     /// Build the request text to pass to Overpass API
     pub fn get_request_txt(&self, with_newline: Option<bool>) -> String {
 
@@ -71,39 +124,63 @@ impl RequestBuilder {
 
 
         if self.get_building {
-            request.push_str(format!("way[building]({});+out+center;\n", bbox_str).as_str());
+            request.push_str(format!("(\nway[building]({});\nnode(w);\n);\nout;\n", bbox_str).as_str());
         }
 
         else {
             // List every level of detail required
             request.push_str("(\n(\n");
 
-            let detail_lvl = RequestBuilder::get_lvl_details(self.bounding_box.dim_km[0]);
-            let way_types = style::get_way_types(detail_lvl);
+            let way_types = style::get_way_types(self.detail_lvl, &self.layers);
 
             for way_type in &way_types {
 
-                // Check if the given way_type is for a highway or a waterway.
-                // way_index between 0 and 25 are highways, way_index greater than 25 are waterway.
-                if style::get_way_index(way_type).unwrap() > 25 {
-                    request.push_str(format!("way[waterway={}]({});\n", way_type, bbox_str).as_str());
-                }
-                else {
-                    request.push_str(format!("way[highway={}]({});\n", way_type, bbox_str).as_str());
+                // Check if the given way_type is for a highway, a waterway or a railway
+                match style::get_way_layer(way_type) {
+                    Some(style::Layer::Water) => request.push_str(format!("way[waterway={}]({});\n", way_type, bbox_str).as_str()),
+                    Some(style::Layer::Rail) => request.push_str(format!("way[railway={}]({});\n", way_type, bbox_str).as_str()),
+                    _ => request.push_str(format!("way[highway={}]({});\n", way_type, bbox_str).as_str()),
                 }
+            }
+
+            // Filled areas (lakes, forests, parks...) are closed ways, or multipolygon relations
+            for (key, value) in style::get_area_types(self.detail_lvl, &self.layers) {
+                request.push_str(format!("way[{}={}]({});\n", key, value, bbox_str).as_str());
+                request.push_str(format!("relation[type=multipolygon][{}={}]({});\n", key, value, bbox_str).as_str());
+            }
 
-                
+            for admin_level in style::get_admin_levels(self.detail_lvl, &self.layers) {
+                request.push_str(format!("relation[boundary=administrative][admin_level={}]({});\n", admin_level, bbox_str).as_str());
+            }
+
+            if style::ElementFilter::new(self.detail_lvl, &self.layers, &[]).routes {
+                for route_type in style::ROUTE_TYPES {
+                    request.push_str(format!("relation[type=route][route={}]({});\n", route_type, bbox_str).as_str());
+                }
             }
 
             // request.push_str(format!(");\nnode(w)({});\n);\nout;\n", bbox_str).as_str());
-            request.push_str(");\nnode(w);\n);\nout;\n");
+            // Recurse down to get the ways of the relations, and the nodes of every way
+            request.push_str(");\n>;\n");
+
+            // Points of interest are nodes on their own
+            for poi_class in &self.poi_classes {
+                let (key, values) = poi_class.tags();
+                for value in values {
+                    request.push_str(format!("node[{}={}]({});\n", key, value, bbox_str).as_str());
+                }
+            }
+
+            // Named places (cities, towns...) too
+            for place_type in style::get_place_types(self.detail_lvl, &self.layers) {
+                request.push_str(format!("node[place={}][name]({});\n", place_type, bbox_str).as_str());
+            }
+
+            request.push_str(");\nout;\n");
 
         }
 
-        let keep_newline: bool = match with_newline {
-            Some(value) => value,
-            None => false,
-        };
+        let keep_newline: bool = with_newline.unwrap_or_default();
 
         if keep_newline {request}
         else {request.replace('\n', "")}
@@ -123,67 +200,207 @@ impl RequestBuilder {
 
 
 
+/// HTTP client sending requests to a list of equivalent endpoints (mirrors of the same API).
+///
+/// Requests failing because of a network error or a temporary server error (429, 502, 503, 504)
+/// are retried with an exponential backoff, honoring the Retry-After header sent by the server.
+/// When every retry failed, the next endpoint of the list is tried.
+pub struct Fetcher {
+    client: reqwest::blocking::Client,
+    endpoints: Vec<String>,
+    max_retries: u32,
+    base_delay: Duration,
+}
+
+impl Fetcher {
+
+    /// Return a new Fetcher sending requests to the given endpoints, in this order
+    pub fn new(endpoints: Vec<String>, max_retries: u32) -> Fetcher {
+        let client = reqwest::blocking::Client::builder().user_agent(APP_USER_AGENT).build().unwrap();
+        Fetcher {
+            client,
+            endpoints,
+            max_retries,
+            base_delay: Duration::from_millis(DEFAULT_RETRY_DELAY_MS),
+        }
+    }
+
+
+
+    /// Set the delay before the first retry. It is doubled at each new retry, up to MAX_RETRY_DELAY.
+    pub fn set_base_delay(&mut self, value: Duration) {
+        self.base_delay = value;
+    }
+
+
+
+    /// Send a GET request with the given query string to the endpoints, until one of them answers successfully.
+    /// Return the body of the response, or the last error if every endpoint failed.
+    pub fn get(&self, query: &str) -> Result<String, Error> {
+        let mut last_error = Error::NoEndpoint;
+
+        for endpoint in &self.endpoints {
+            let url = format!("{}?{}", endpoint, query);
+
+            for attempt in 0..=self.max_retries {
+                let retry_after = match self.get_once(&url) {
+                    Ok(body) => return Ok(body),
+                    Err((err, retry_after)) => {
+                        let retryable = is_retryable(&err);
+                        last_error = err;
+
+                        // Errors caused by the request itself won't be fixed by another endpoint
+                        if !retryable {return Err(last_error);}
+                        retry_after
+                    },
+                };
+
+                // No more retries on this endpoint
+                if attempt == self.max_retries {break;}
+
+                // Wait the time asked by the server, or an exponentially increasing delay.
+                // If the server asks to wait too long, go to the next endpoint
+                let delay = retry_after.unwrap_or_else(|| self.backoff_delay(attempt));
+                if delay > MAX_RETRY_DELAY {break;}
+                thread::sleep(delay);
+            }
+        }
+
+        Err(last_error)
+    }
+
+
+
+    /// Return the delay to wait after the given failed attempt (from 0): the base delay doubled at each attempt,
+    /// up to MAX_RETRY_DELAY
+    fn backoff_delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.checked_pow(attempt).unwrap_or(u32::MAX);
+        self.base_delay.saturating_mul(factor).min(MAX_RETRY_DELAY)
+    }
+
+
+
+    /// Send a single GET request to the given URL and return the body of the response.
+    /// On failure, also return the delay asked by the server before retrying, if any.
+    fn get_once(&self, url: &str) -> Result<String, (Error, Option<Duration>)> {
+        let response = self.client.get(url).send().map_err(|err| (Error::from(err), None))?;
+        let status = response.status();
+
+        if !status.is_success() {
+            let retry_after = response.headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_retry_after);
+
+            let err = if status == reqwest::StatusCode::TOO_MANY_REQUESTS {Error::RateLimited}
+                      else {Error::HttpStatus(status.as_u16())};
+
+            return Err((err, retry_after));
+        }
+
+        response.text().map_err(|err| (Error::from(err), None))
+    }
+}
+
+
+
+/// Return true if the request that failed with the given error may succeed if sent again
+fn is_retryable(err: &Error) -> bool {
+    match err {
+        Error::Network(_) | Error::RateLimited => true,
+        Error::HttpStatus(status) => matches!(status, 502..=504),
+        _ => false,
+    }
+}
+
+
+
+/// Parse the value of a Retry-After header, which is either a number of seconds or an HTTP date
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = httpdate::parse_http_date(value.trim()).ok()?;
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+
+
+
+
+
 /// Struct used to store nominatim cache and to make requests to nominatim
 pub struct Searcher {
     cache: HashMap<String, String>,
-    client: reqwest::blocking::Client,
+    disk_cache: DiskCache,
+    fetcher: Fetcher,
 }
 
 impl Searcher {
 
-    /// Return a new Searcher
-    pub fn new() -> Searcher {
-        let client = reqwest::blocking::Client::builder().user_agent(APP_USER_AGENT).build().unwrap();
+    /// Return a new Searcher sending its requests with the given fetcher. Responses are also stored in the given disk cache.
+    pub fn new(fetcher: Fetcher, disk_cache: DiskCache) -> Searcher {
         Searcher {
             cache: HashMap::new(),
-            client: client,
+            disk_cache,
+            fetcher,
         }
     }
 
-    /// Takes a search string and returns the OSM id of the first returned area.
-    pub fn research(&mut self, search: &str) -> Result<geo::BoundingBox, io::Error> {
+    /// Takes a search string and returns the bounding box of the first returned area.
+    pub fn research(&mut self, search: &str) -> Result<geo::BoundingBox, Error> {
 
         let result: String;
+        let mut is_fetched = false;
 
         // Check if the search string is already in the cache
-        if self.cache.contains_key(&search.to_string()) {
-            result = match self.cache.get(&search.to_string()) {
+        if self.cache.contains_key(search) {
+            result = match self.cache.get(search) {
                 Some(value) => value.clone(),
                 None => "".to_string(),
             };
         }
-        // Make a request to the Nominatim API and cache it.
+        // Check if the search string is in the disk cache
+        else if let Some(value) = self.disk_cache.get(NOMINATIM_CACHE_NAMESPACE, search) {
+            result = value;
+            self.cache.insert(search.to_string(), result.clone());
+        }
+        // Make a request to the Nominatim API. It is cached once it is known to be valid.
         else {
-            // create the URL from search string and api url
-            let request_url = format!("{}?q={}&format=json&limit=1", NOMINATIM_API_URL, search.replace(" ", "+"));
+            // create the query string from search string
+            let query = format!("q={}&format=json&limit=1", search.replace(' ', "+"));
 
             // request and store result
-            let response = self.client.get(request_url).send().unwrap();
-            result = response.text().unwrap();
-
-            // cache the result for future use
-            self.cache.insert(search.to_string(), result.clone());
+            result = self.fetcher.get(&query)?;
+            is_fetched = true;
         }
 
         // Parse the result
-        let first_json_value: &json::JsonValue = &json::parse(&result).unwrap()[0];
-        
+        let json_value = json::parse(&result)?;
+        if !json_value.is_array() {return Err(Error::MalformedJson(String::from("Nominatim did not return a list of places")));}
+        if json_value.is_empty() {return Err(Error::NoResult(search.to_string()));}
+
         // Get bounding box returned by Nominatim
-        let bbox_of_jsonvalue = &first_json_value["boundingbox"];
+        let bbox_of_jsonvalue = &json_value[0]["boundingbox"];
 
         // Transform the returned string values as f64
         let mut bbox_of_f64: [f64; 4] = [0.0, 0.0, 0.0, 0.0];
-        for i in 0..4 {
-            bbox_of_f64[i] = match bbox_of_jsonvalue[i].as_str().unwrap().parse::<f64>() {
-                Ok(value) => value,
-                _ => {
-                    // Raise error if the value is not a valid f64
-                    println!("[ERROR]: The bounding box returned by Nominatim is not valid.");
-                    return Err(io::Error::new(io::ErrorKind::Other, "[ERROR]: The bounding box returned by Nominatim is not valid."));
-                },
+        for (i, value) in bbox_of_f64.iter_mut().enumerate() {
+            *value = match bbox_of_jsonvalue[i].as_str().map(|value| value.parse::<f64>()) {
+                Some(Ok(value)) => value,
+
+                // Raise error if the value is not a valid f64
+                _ => return Err(Error::MalformedJson(String::from("The bounding box returned by Nominatim is not valid"))),
             }
         }
 
+        // Cache the valid result for future use
+        if is_fetched {
+            self.cache.insert(search.to_string(), result.clone());
+            self.disk_cache.insert(NOMINATIM_CACHE_NAMESPACE, search, &result);
+        }
+
         // Build the BoundingBox and return it
         // (we're using the minlat minlon maxlat maxlon order, instead of the maxlat maxlon minlat minlon order given by Nominatim)
         Ok(geo::BoundingBox::new(bbox_of_f64[0], bbox_of_f64[2], bbox_of_f64[1], bbox_of_f64[3]))
@@ -200,30 +417,47 @@ impl Searcher {
 /// Struct used to store overpass cache and to make requests to overpass
 pub struct OverpassData {
     cache: HashMap<String, String>,
-    client: reqwest::blocking::Client,
+    disk_cache: DiskCache,
+    fetcher: Fetcher,
+    timeout: u32,
 }
 
 impl OverpassData {
 
-    /// Return a newly created OverpassData struct
-    pub fn new() -> OverpassData {
-        let client = reqwest::blocking::Client::builder().user_agent(APP_USER_AGENT).build().unwrap();
+    /// Return a newly created OverpassData struct, sending its requests with the given fetcher.
+    /// Responses are also stored in the given disk cache.
+    pub fn new(fetcher: Fetcher, disk_cache: DiskCache) -> OverpassData {
         OverpassData {
             cache: HashMap::new(),
-            client: client,
+            disk_cache,
+            fetcher,
+            timeout: DEFAULT_OVERPASS_TIMEOUT,
         }
     }
 
 
 
+    /// Set the timeout (in seconds) of the requests, sent to Overpass with the query
+    pub fn set_timeout(&mut self, value: u32) {
+        self.timeout = value;
+    }
+
+
+
 
     /// Takes a bounding box as parameter (min lat, min long, max lat, max long) and returns the result of the Overpass API.
+    /// Only the ways and points of interest kept by the given filter are requested, or only the buildings if get_building is true.
     /// The result is cached for future use.
-    pub fn request(&mut self, bounding_box: geo::BoundingBox, timeout: u32) -> String {
+    pub fn request(&mut self, bounding_box: geo::BoundingBox, element_filter: &style::ElementFilter, get_building: bool) -> Result<String, Error> {
         let result: String;
 
-        // Format the key used in the cache hashmap
-        let key = format!("{},{},{},{}", bounding_box.coo[0], bounding_box.coo[1], bounding_box.coo[2], bounding_box.coo[3]);
+        // Generate request data. It is used as the key of the caches, as it contains every parameter of the request
+        let mut request_builder = RequestBuilder::new(bounding_box, self.timeout);
+        request_builder.set_detail_lvl(element_filter.detail_lvl);
+        request_builder.set_layers(&element_filter.layers);
+        request_builder.set_poi_classes(&element_filter.poi_classes);
+        request_builder.get_building(get_building);
+        let key = request_builder.get_request_txt(None);
 
         // Check if the result is already in the cache
         if self.cache.contains_key(&key) {
@@ -235,21 +469,180 @@ impl OverpassData {
 
         // If not cached, make a request to the Overpass API and cache the result
         else {
-            // Generate request data
-            let request_builder = RequestBuilder::new(bounding_box, timeout);
-            let request_data = request_builder.get_request_txt(None);
-
-            // Create the URL of the request
-            let request_url = format!("{}?data={}", OVERPASS_API_URL, request_data);
+            if let Some(value) = self.disk_cache.get(OVERPASS_CACHE_NAMESPACE, &key) {
+                result = value;
+            }
+            else {
+                // request and store result. Failed queries are not cached
+                result = self.fetcher.get(&format!("data={}", key))?;
+                check_overpass_remark(&result)?;
 
-            // request and store result
-            let response = self.client.get(request_url).send().unwrap();
-            result = response.text().unwrap();
+                self.disk_cache.insert(OVERPASS_CACHE_NAMESPACE, &key, &result);
+            }
 
             // cache the result for future use
             self.cache.insert(key, result.clone());
         }
 
-        result
+        Ok(result)
+    }
+}
+
+
+impl DataSource for OverpassData {
+    fn load(&mut self, bounding_box: geo::BoundingBox, element_filter: &style::ElementFilter) -> Result<OsmData, Error> {
+        let mut data = OsmData::default();
+
+        // Buildings are requested separately from the other ways and the points of interest
+        if !element_filter.way_types.is_empty() || !element_filter.area_types.is_empty() || element_filter.routes || !element_filter.admin_levels.is_empty()
+            || !element_filter.poi_classes.is_empty() || !element_filter.place_types.is_empty() {
+            data.append(OsmData::from_json(&self.request(bounding_box, element_filter, false)?)?);
+        }
+        if element_filter.buildings {
+            data.append(OsmData::from_json(&self.request(bounding_box, element_filter, true)?)?);
+        }
+
+        Ok(data)
+    }
+}
+
+
+
+
+
+/// Return an error if the given Overpass response contains a runtime error remark
+/// (returned by Overpass with a success status when the query timed out or ran out of memory).
+fn check_overpass_remark(response: &str) -> Result<(), Error> {
+
+    // Avoid parsing the whole response when there is no remark
+    if !response.contains("\"remark\"") {return Ok(());}
+
+    let json_value = json::parse(response)?;
+    match json_value["remark"].as_str() {
+        Some(remark) if remark.contains("error") => Err(Error::OverpassRemark(remark.to_string())),
+        _ => Ok(()),
+    }
+}
+
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Instant;
+
+
+    /// Start a stub HTTP server on 127.0.0.1 answering the given raw responses in order, then always the last one.
+    /// Return its URL and the number of requests it received.
+    fn stub_server(responses: &[&str]) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/api", listener.local_addr().unwrap());
+        let responses: Vec<String> = responses.iter().map(|response| response.to_string()).collect();
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        let counter = Arc::clone(&requests);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {Ok(stream) => stream, Err(_) => continue};
+
+                // Read the request until the empty line ending its headers
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).is_ok_and(|size| size > 0) && line != "\r\n" {line.clear();}
+
+                let index = counter.fetch_add(1, Ordering::SeqCst).min(responses.len() - 1);
+                let _ = stream.write_all(responses[index].as_bytes());
+            }
+        });
+
+        (url, requests)
+    }
+
+
+    /// Return a raw HTTP response with the given status line, extra headers and body
+    fn response(status: &str, headers: &str, body: &str) -> String {
+        format!("HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}", status, headers, body.len(), body)
+    }
+
+
+    fn fetcher(endpoints: &[&str], max_retries: u32) -> Fetcher {
+        let mut fetcher = Fetcher::new(endpoints.iter().map(|endpoint| endpoint.to_string()).collect(), max_retries);
+        fetcher.set_base_delay(Duration::ZERO);
+        fetcher
+    }
+
+
+
+    #[test]
+    fn retry_after_is_honored() {
+        let rate_limited = response("429 Too Many Requests", "Retry-After: 1\r\n", "");
+        let ok = response("200 OK", "", "data");
+        let (url, requests) = stub_server(&[&rate_limited, &ok]);
+
+        let start = Instant::now();
+        assert_eq!(fetcher(&[&url], 3).get("q=1").unwrap(), "data");
+        assert!(start.elapsed() >= Duration::from_secs(1));
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+
+    #[test]
+    fn too_long_retry_after_goes_to_next_endpoint() {
+        let rate_limited = response("429 Too Many Requests", "Retry-After: 3600\r\n", "");
+        let (first_url, first_requests) = stub_server(&[&rate_limited]);
+        let (second_url, _) = stub_server(&[&response("200 OK", "", "second")]);
+
+        assert_eq!(fetcher(&[&first_url, &second_url], 3).get("q=1").unwrap(), "second");
+        assert_eq!(first_requests.load(Ordering::SeqCst), 1);
+    }
+
+
+    #[test]
+    fn failover_after_server_errors() {
+        let (first_url, first_requests) = stub_server(&[&response("503 Service Unavailable", "", "")]);
+        let (second_url, second_requests) = stub_server(&[&response("200 OK", "", "second")]);
+
+        assert_eq!(fetcher(&[&first_url, &second_url], 2).get("q=1").unwrap(), "second");
+        assert_eq!(first_requests.load(Ordering::SeqCst), 3);
+        assert_eq!(second_requests.load(Ordering::SeqCst), 1);
+    }
+
+
+    #[test]
+    fn every_endpoint_failing_returns_the_last_error() {
+        let (first_url, _) = stub_server(&[&response("502 Bad Gateway", "", "")]);
+        let (second_url, _) = stub_server(&[&response("504 Gateway Timeout", "", "")]);
+
+        assert!(matches!(fetcher(&[&first_url, &second_url], 1).get("q=1"), Err(Error::HttpStatus(504))));
+    }
+
+
+    #[test]
+    fn client_errors_are_returned_immediately() {
+        let (first_url, first_requests) = stub_server(&[&response("400 Bad Request", "", "")]);
+        let (second_url, second_requests) = stub_server(&[&response("200 OK", "", "second")]);
+
+        assert!(matches!(fetcher(&[&first_url, &second_url], 3).get("q=1"), Err(Error::HttpStatus(400))));
+        assert_eq!(first_requests.load(Ordering::SeqCst), 1);
+        assert_eq!(second_requests.load(Ordering::SeqCst), 0);
+    }
+
+
+    #[test]
+    fn backoff_delay_is_capped() {
+        let mut fetcher = fetcher(&[], 0);
+        fetcher.set_base_delay(Duration::from_millis(500));
+
+        assert_eq!(fetcher.backoff_delay(0), Duration::from_millis(500));
+        assert_eq!(fetcher.backoff_delay(2), Duration::from_secs(2));
+        assert_eq!(fetcher.backoff_delay(31), MAX_RETRY_DELAY);
+        assert_eq!(fetcher.backoff_delay(40), MAX_RETRY_DELAY);
+        assert_eq!(fetcher.backoff_delay(u32::MAX), MAX_RETRY_DELAY);
     }
 }
\ No newline at end of file
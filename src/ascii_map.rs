@@ -1,20 +1,33 @@
 use crate::geo::BoundingBox;
+use crate::cell::{self, Cell, ColorMode};
+
+use unicode_width::UnicodeWidthStr;
+
+
+
+/// Height of a character divided by its width, in most terminals
+pub const CELL_RATIO: f64 = 2.0;
+
+/// Maximum width of the scale, at the start of the caption (in characters)
+pub const SCALE_WIDTH: usize = 18;
 
 
 
 pub struct AsciiMap {
     display_box: BoundingBox,
-    data: Vec<Vec<String>>,
+    data: Vec<Vec<Cell>>,
     height: u32,
     pub width: u32,
     is_decorated: bool,
+    legend: Vec<(Cell, String)>,    // Symbols displayed on the map, with their description
+    color_mode: ColorMode,          // How the colors are written when the map is printed
 }
 
 
 impl AsciiMap {
 
     /// Return a new AsciiMap
-    pub fn from(display_box: BoundingBox, data: Vec<Vec<String>>) -> AsciiMap {
+    pub fn from(display_box: BoundingBox, data: Vec<Vec<Cell>>) -> AsciiMap {
         let height = data.len() as u32;
         let width = data[0].len() as u32;
 
@@ -24,75 +37,65 @@ impl AsciiMap {
             height,
             width,
             is_decorated: false,
+            legend: Vec::new(),
+            color_mode: ColorMode::Ansi,
         }
     }
 
 
 
-    /// Double each character of the asciimap.
-    /// The width will be doubled.
-    /// It makes the map more orthogonal when displayed in a terminal.
-    pub fn double(&mut self) {
-        let mut new_data: Vec<Vec<String>> = Vec::new();
+    /// Set the legend of the map: the symbols displayed on it (with their colors), and their description
+    pub fn set_legend(&mut self, legend: Vec<(Cell, String)>) {
+        self.legend = legend;
+    }
 
-        for x in 0..self.height {
-            new_data.push(Vec::new());
 
-            // Add 2 times the characters
-            for y in 0..self.width {
-                new_data[x as usize].push(String::from(&self.data[x as usize][y as usize]));
-                //new_data[x as usize].push(String::from(&self.data[x as usize][y as usize]));
-                new_data[x as usize].push(String::from(" "));
-            }
-        }
 
-        self.data = new_data;
-        self.width = self.width * 2;
+    /// Change how the colors are written when the map is printed (by default, with the 16 ansi colors)
+    pub fn set_color_mode(&mut self, color_mode: ColorMode) {
+        self.color_mode = color_mode;
     }
 
 
 
-
     /// Return a copy of this AsciiMap, with added decoration (border, overlay)
     pub fn with_decoration(&self) -> AsciiMap {
-        let mut new_data: Vec<Vec<String>> = Vec::new();
-        new_data.push(Vec::new());
+        let mut new_data: Vec<Vec<Cell>> = Vec::new();
 
-        // Add the top border
-        new_data[0].push(String::from("╚"));
-        for _ in 0..self.width {new_data[0].push(String::from("═"));}
-        new_data[0].push(String::from("╝"));
+        // Add the bottom border (rows go from south to north)
+        let mut border: Vec<Cell> = vec![Cell::new('╚')];
+        border.extend((0..self.width).map(|_| Cell::new('═')));
+        border.push(Cell::new('╝'));
+        new_data.push(border);
 
         // Add the whole ascii map, with borders on the left and the right
-        for x in 1..self.height as usize {
-            new_data.push(Vec::new());
-
-            new_data[x].push(String::from("║"));
-
-            for y in 0..self.width as usize {
-                new_data[x].push(String::from(&self.data[x][y]));
-            }
-
-            new_data[x].push(String::from("║"));
+        for row in &self.data {
+            let mut line: Vec<Cell> = vec![Cell::new('║')];
+            line.extend(row.iter().cloned());
+            line.push(Cell::new('║'));
+            new_data.push(line);
         }
 
-        new_data.push(Vec::new());
-
-        // Add the bottom border
-        new_data[self.height as usize].push(String::from("╔"));
-        for _ in 0..self.width {new_data[self.height as usize].push(String::from("═"));}
-        new_data[self.height as usize].push(String::from("╗"));
+        // Add the top border
+        let mut border: Vec<Cell> = vec![Cell::new('╔')];
+        border.extend((0..self.width).map(|_| Cell::new('═')));
+        border.push(Cell::new('╗'));
+        new_data.push(border);
 
 
 
-        // Add the compass rose (the north arrow) on the map
-        new_data[3][(self.width - 3) as usize] = String::from("⇯");
-        new_data[2][(self.width - 3) as usize] = String::from("N");
+        // Add the compass rose (the north arrow) on the map, if there is room for it
+        if self.height >= 3 && self.width >= 4 {
+            new_data[3][(self.width - 3) as usize] = Cell::new('⇯');
+            new_data[2][(self.width - 3) as usize] = Cell::new('N');
+        }
 
 
         // Return the decorated AsciiMap
         let mut res = AsciiMap::from(self.display_box, new_data);
         res.is_decorated = true;
+        res.legend = self.legend.clone();
+        res.color_mode = self.color_mode;
         res
     }
 
@@ -101,7 +104,8 @@ impl AsciiMap {
 
     /// Return the approximate distance a character represents (in km)
     fn get_scale(&self) -> f64 {
-        self.display_box.dim_km[0] / self.width as f64
+        let map_width = if self.is_decorated {self.width - 2} else {self.width};
+        self.display_box.dim_km[1] / map_width as f64
     }
 
 
@@ -120,7 +124,7 @@ impl AsciiMap {
         if scale_value < 10.0 {
             let number_rounded = (scale_value * 100.0).round() as u64 * 10;
             scale_repr.push_str(&number_rounded.to_string());
-            scale_repr.push_str("m");
+            scale_repr.push('m');
         }
         else {
             let number_rounded = (scale_value * 10.0).round() as u64 / 10;
@@ -135,16 +139,63 @@ impl AsciiMap {
 
 
 
-    /// Print the ascii map to the terminal
-    pub fn print(&self) {
-        for x in 0..(self.height) as usize {
-            for y in 0..(self.width) as usize {
-                print!("{}", self.data[self.height as usize - x - 1][y]);
+    /// Return the scale of this map followed by as many entries of the legend as the width of the map allows
+    fn get_caption(&self) -> Vec<Cell> {
+        let mut caption: Vec<Cell> = self.get_scale_repr().chars().map(Cell::new).collect();
+
+        for (symbol, description) in &self.legend {
+
+            let entry_width = legend_entry_width(description);
+            if caption.len() + entry_width > self.width as usize {break;}
+
+            caption.extend("   ".chars().map(Cell::new));
+            caption.push(*symbol);
+            caption.push(Cell::blank());
+            for (c, width) in cell::display_chars(description) {
+                caption.push(Cell::new(c));
+                if width == 2 {caption.push(Cell::new(c).continuation());}
             }
-            print!("\n");
         }
 
-        // Print the scale
-        println!("{}", self.get_scale_repr());
+        caption
+    }
+
+
+
+
+
+    /// Return the lines of the ascii map as they must be displayed (north on top),
+    /// followed by the line representing the scale and the legend
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = Vec::new();
+
+        for x in 0..(self.height) as usize {
+            lines.push(cell::to_line(&self.data[self.height as usize - x - 1], self.color_mode));
+        }
+
+        // Add the scale and the legend
+        lines.push(cell::to_line(&self.get_caption(), self.color_mode));
+
+        lines
+    }
+
+
+
+    /// Print the ascii map to the terminal
+    pub fn print(&self) {
+        for line in self.lines() {
+            println!("{}", line);
+        }
     }
+}
+
+
+
+
+
+/// Return the width of an entry of the legend with the given description, in the caption (in columns of the terminal)
+pub fn legend_entry_width(description: &str) -> usize {
+
+    // The symbols are one character wide
+    3 + 2 + description.width()
 }
\ No newline at end of file
@@ -0,0 +1,246 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+
+
+/// Name of the directory of Rustreet in the user cache directory
+static CACHE_DIR_NAME: &str = "rustreet";
+
+/// Default time after which a cached response is considered outdated (one week)
+pub const DEFAULT_TTL_HOURS: u64 = 24 * 7;
+
+/// Default maximum size of the cache directory, in megabytes
+pub const DEFAULT_MAX_SIZE_MB: u64 = 100;
+
+
+
+
+
+
+/// On-disk cache of the responses of the APIs, shared by the Nominatim and Overpass clients.
+///
+/// Each entry is stored in its own file, `<cache dir>/<namespace>/<hash of the key>`. The first line
+/// of the file is the key itself, so hash collisions are detected. Entries older than the TTL are ignored.
+/// The outdated entries are removed at the first insertion, and the oldest entries when the cache grows larger
+/// than its maximum size.
+#[derive(Clone)]
+pub struct DiskCache {
+    dir: Option<PathBuf>,           // Root directory of the cache. None if the cache is disabled
+    ttl: Duration,                  // Time after which an entry is outdated
+    max_size: u64,                  // Maximum size of the cache directory, in bytes
+    refresh: bool,                  // If true, entries are never read (but still written)
+    size: Arc<Mutex<Option<u64>>>,  // Size of the cache directory, in bytes, once listed by evict. Shared by the clones
+}
+
+
+impl DiskCache {
+
+    /// Return a new DiskCache stored in the user cache directory ($XDG_CACHE_HOME/rustreet on Linux).
+    /// Huge values of the time to live or of the maximum size saturate, so they mean no limit.
+    /// The cache is disabled if no cache directory can be found.
+    pub fn new(ttl_hours: u64, max_size_mb: u64) -> DiskCache {
+        DiskCache {
+            dir: dirs::cache_dir().map(|dir| dir.join(CACHE_DIR_NAME)),
+            ttl: Duration::from_secs(ttl_hours.saturating_mul(3600)),
+            max_size: max_size_mb.saturating_mul(1024 * 1024),
+            refresh: false,
+            size: Arc::new(Mutex::new(None)),
+        }
+    }
+
+
+
+    /// Return a DiskCache that never stores nor returns anything
+    pub fn disabled() -> DiskCache {
+        DiskCache {dir: None, ttl: Duration::ZERO, max_size: 0, refresh: false, size: Arc::new(Mutex::new(None))}
+    }
+
+
+
+    /// Set whether the existing entries must be ignored. The responses are still written to the cache,
+    /// so they replace the outdated ones.
+    pub fn set_refresh(&mut self, value: bool) {
+        self.refresh = value;
+    }
+
+
+
+    /// Return the path of the file of the given entry
+    fn entry_path(&self, namespace: &str, key: &str) -> Option<PathBuf> {
+        self.dir.as_ref().map(|dir| dir.join(namespace).join(format!("{:016x}", hash(key))))
+    }
+
+
+
+    /// Return the cached value of the given key, if it exists and is not outdated
+    pub fn get(&self, namespace: &str, key: &str) -> Option<String> {
+        if self.refresh {return None;}
+
+        let path = self.entry_path(namespace, key)?;
+
+        // Ignore outdated entries
+        let age = fs::metadata(&path).ok()?.modified().ok()?.elapsed().unwrap_or_default();
+        if age > self.ttl {return None;}
+
+        // The first line of the file is the key, the rest is the value
+        let content = fs::read_to_string(&path).ok()?;
+        let (stored_key, value) = content.split_once('\n')?;
+
+        if stored_key == key {Some(value.to_string())}
+        else {None}
+    }
+
+
+
+    /// Store the value of the given key in the cache. The entries are only listed to evict the old ones
+    /// at the first insertion, and when the cache grows too large.
+    /// Errors are ignored: the cache is only an optimisation.
+    pub fn insert(&self, namespace: &str, key: &str, value: &str) {
+        let path = match self.entry_path(namespace, key) {
+            Some(path) => path,
+            None => return,
+        };
+
+        // Keys containing a newline can't be stored
+        if key.contains('\n') {return;}
+
+        if let Some(parent) = path.parent() {
+            if fs::create_dir_all(parent).is_err() {return;}
+        }
+
+        let replaced_size = fs::metadata(&path).map(|metadata| metadata.len()).unwrap_or(0);
+        let content = format!("{}\n{}", key, value);
+        if fs::write(&path, &content).is_err() {return;}
+
+        // The entries are listed again if the size is unknown (after an error)
+        let mut cache_size = match self.size.lock() {
+            Ok(cache_size) => cache_size,
+            Err(_) => return,
+        };
+        *cache_size = cache_size.map(|cache_size| cache_size.saturating_sub(replaced_size).saturating_add(content.len() as u64));
+        if cache_size.is_none_or(|cache_size| cache_size > self.max_size) {
+            *cache_size = self.evict().ok();
+        }
+    }
+
+
+
+    /// Remove the outdated entries, then the oldest ones until the cache is smaller than its maximum size.
+    /// Return the size of the remaining entries.
+    fn evict(&self) -> io::Result<u64> {
+        let dir = match &self.dir {
+            Some(dir) => dir,
+            None => return Ok(0),
+        };
+
+        // List every entry of every namespace, with its size and modification time
+        let mut entries: Vec<(PathBuf, u64, SystemTime)> = Vec::new();
+        for namespace in fs::read_dir(dir)? {
+            let namespace = namespace?;
+            if !namespace.file_type()?.is_dir() {continue;}
+
+            for entry in fs::read_dir(namespace.path())? {
+                let entry = entry?;
+                let metadata = entry.metadata()?;
+                entries.push((entry.path(), metadata.len(), metadata.modified()?));
+            }
+        }
+
+        // Oldest entries first
+        entries.sort_by_key(|entry| entry.2);
+
+        let mut total_size: u64 = entries.iter().map(|entry| entry.1).sum();
+        for (path, size, modified) in entries {
+            let outdated = modified.elapsed().unwrap_or_default() > self.ttl;
+            if !outdated && total_size <= self.max_size {break;}
+
+            fs::remove_file(path)?;
+            total_size -= size;
+        }
+
+        Ok(total_size)
+    }
+}
+
+
+
+
+
+
+/// Return the 64-bit FNV-1a hash of the given string.
+/// Used instead of the std hasher, whose output is not guaranteed to be stable between Rust versions.
+fn hash(value: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in value.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+
+
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    /// Return a cache in a new temporary directory, of the given maximum size in bytes
+    fn temporary_cache(name: &str, max_size: u64) -> DiskCache {
+        let dir = std::env::temp_dir().join(format!("rustreet-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        DiskCache {dir: Some(dir), ttl: Duration::from_secs(3600), max_size, refresh: false, size: Arc::new(Mutex::new(None))}
+    }
+
+
+    fn entry_count(cache: &DiskCache, namespace: &str) -> usize {
+        fs::read_dir(cache.dir.as_ref().unwrap().join(namespace)).unwrap().count()
+    }
+
+
+
+    #[test]
+    fn entries_are_only_listed_when_the_cache_is_too_large() {
+        let cache = temporary_cache("eviction", 100);
+
+        // Each entry is 20 bytes: a key of 3 characters, a newline and a value of 16 characters
+        cache.insert("test", "k00", "0123456789abcdef");
+        assert_eq!(*cache.size.lock().unwrap(), Some(20));
+
+        // Files written by another process are not counted until the entries are listed again
+        std::thread::sleep(Duration::from_millis(10));
+        fs::write(cache.dir.as_ref().unwrap().join("test").join("other"), [0u8; 50]).unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        cache.insert("test", "k01", "0123456789abcdef");
+        assert_eq!(*cache.size.lock().unwrap(), Some(40));
+
+        // Replacing an entry doesn't change the size
+        cache.clone().insert("test", "k01", "0123456789abcdef");
+        assert_eq!(*cache.size.lock().unwrap(), Some(40));
+        assert_eq!(entry_count(&cache, "test"), 3);
+
+        // Going over the maximum size lists the entries, and removes the oldest ones
+        for i in 2..5 {
+            std::thread::sleep(Duration::from_millis(10));
+            cache.insert("test", &format!("k{:02}", i), "0123456789abcdef");
+        }
+        assert_eq!(*cache.size.lock().unwrap(), Some(100));
+        assert_eq!(entry_count(&cache, "test"), 6);
+
+        std::thread::sleep(Duration::from_millis(10));
+        cache.insert("test", "k05", "0123456789abcdef");
+        assert_eq!(*cache.size.lock().unwrap(), Some(100));
+        assert_eq!(entry_count(&cache, "test"), 5);
+        assert!(cache.get("test", "k00").is_none());
+        assert_eq!(cache.get("test", "k05").as_deref(), Some("0123456789abcdef"));
+
+        let _ = fs::remove_dir_all(cache.dir.as_ref().unwrap());
+    }
+}
\ No newline at end of file
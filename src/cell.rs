@@ -0,0 +1,323 @@
+use crate::osm::MemberType;
+
+use std::io::IsTerminal;
+use unicode_width::UnicodeWidthChar;
+
+
+
+/// A color of the 16 colors palette of the terminals
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Color {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    BrightBlack,
+    BrightRed,
+    BrightGreen,
+    BrightYellow,
+    BrightBlue,
+    BrightMagenta,
+    BrightCyan,
+    BrightWhite,
+}
+
+
+/// Every color, with its name in the themes
+const COLORS: [(&str, Color); 16] = [
+    ("black", Color::Black), ("red", Color::Red), ("green", Color::Green), ("yellow", Color::Yellow),
+    ("blue", Color::Blue), ("magenta", Color::Magenta), ("cyan", Color::Cyan), ("white", Color::White),
+    ("bright-black", Color::BrightBlack), ("bright-red", Color::BrightRed), ("bright-green", Color::BrightGreen), ("bright-yellow", Color::BrightYellow),
+    ("bright-blue", Color::BrightBlue), ("bright-magenta", Color::BrightMagenta), ("bright-cyan", Color::BrightCyan), ("bright-white", Color::BrightWhite),
+];
+
+
+/// Levels of the red, green and blue components of the 6x6x6 color cube of the 256 colors palette of xterm
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+
+impl Color {
+
+    /// Return the color of the given name (ex: "blue", "bright-red"), or None if there is no such color
+    pub fn from_name(name: &str) -> Option<Color> {
+        COLORS.iter().find(|(color_name, _)| *color_name == name).map(|(_, color)| *color)
+    }
+
+
+
+    /// Return the ansi code of this color as a foreground color (30 to 37, then 90 to 97).
+    /// The code of a background color is 10 more.
+    fn ansi_code(&self) -> u8 {
+        let index = *self as u8;
+        if index < 8 {30 + index} else {90 + index - 8}
+    }
+
+
+
+    /// Return the red, green and blue components of this color in truecolor mode.
+    /// The palette is the same on every terminal, where the 16 colors depend on the theme of the terminal,
+    /// and its colors are chosen to be told apart on the map: water is blue, parks are green...
+    fn rgb(&self) -> [u8; 3] {
+        match self {
+            Color::Black => [28, 28, 28],
+            Color::Red => [214, 69, 65],
+            Color::Green => [82, 160, 72],
+            Color::Yellow => [214, 160, 48],
+            Color::Blue => [58, 122, 206],
+            Color::Magenta => [168, 92, 182],
+            Color::Cyan => [40, 176, 190],
+            Color::White => [196, 196, 196],
+            Color::BrightBlack => [118, 118, 118],
+            Color::BrightRed => [255, 107, 94],
+            Color::BrightGreen => [132, 206, 102],
+            Color::BrightYellow => [255, 210, 74],
+            Color::BrightBlue => [104, 164, 255],
+            Color::BrightMagenta => [222, 124, 232],
+            Color::BrightCyan => [98, 218, 230],
+            Color::BrightWhite => [246, 246, 246],
+        }
+    }
+
+
+
+    /// Return the index of the color of the 256 colors palette of xterm closest to the truecolor one (see Color::rgb):
+    /// a color of the 6x6x6 cube (16 to 231) or a gray of the grayscale ramp (232 to 255)
+    fn xterm_index(&self) -> u8 {
+        let rgb = self.rgb();
+        let distance = |candidate: [u8; 3]| (0..3).map(|i| (rgb[i] as i32 - candidate[i] as i32).pow(2)).sum::<i32>();
+
+        // Nearest level of the cube for each component
+        let cube_level = |component: u8| CUBE_LEVELS.iter().enumerate()
+            .min_by_key(|(_, level)| (component as i32 - **level as i32).abs()).map_or(0, |(i, _)| i as u8);
+        let [r, g, b] = rgb.map(cube_level);
+        let cube = [CUBE_LEVELS[r as usize], CUBE_LEVELS[g as usize], CUBE_LEVELS[b as usize]];
+
+        // Nearest gray of the ramp, from the average of the components
+        let average = rgb.iter().map(|component| *component as u16).sum::<u16>() / 3;
+        let gray_step = (average.saturating_sub(3) / 10).min(23) as u8;
+        let gray = 8 + 10 * gray_step;
+
+        if distance([gray; 3]) < distance(cube) {232 + gray_step}
+        else {16 + 36 * r + 6 * g + b}
+    }
+
+
+
+    /// Return the parameters of the ansi escape code displaying this color in the given mode,
+    /// as a foreground color or as a background color
+    fn ansi_params(&self, color_mode: ColorMode, background: bool) -> String {
+        match color_mode {
+            ColorMode::TrueColor => {
+                let [r, g, b] = self.rgb();
+                format!("{};2;{};{};{}", if background {48} else {38}, r, g, b)
+            }
+            ColorMode::Ansi256 => format!("{};5;{}", if background {48} else {38}, self.xterm_index()),
+            _ => (self.ansi_code() + if background {10} else {0}).to_string(),
+        }
+    }
+}
+
+
+
+
+
+
+/// When to color the map, as chosen by the user
+#[derive(clap::ArgEnum, Copy, Clone, PartialEq, Eq)]
+pub enum ColorChoice {
+    Auto,       // Only when the output is a terminal, unless the NO_COLOR environment variable is set
+    Always,
+    Never,
+}
+
+
+impl ColorChoice {
+
+    /// Return the color mode of the output: plain text if colors are not wanted, truecolor if the terminal
+    /// supports it (as announced by the COLORTERM environment variable), the 256 colors of xterm if the terminal
+    /// announces them in the TERM environment variable (ex: xterm-256color), the 16 ansi colors otherwise
+    pub fn color_mode(&self) -> ColorMode {
+        let env_var = |name: &str| std::env::var(name).unwrap_or_default();
+
+        let is_colored = match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+
+            // See https://no-color.org: NO_COLOR disables the colors when it is set to any non-empty value
+            ColorChoice::Auto => std::io::stdout().is_terminal() && env_var("NO_COLOR").is_empty() && env_var("TERM") != "dumb",
+        };
+
+        if !is_colored {ColorMode::Plain}
+        else if ["truecolor", "24bit"].contains(&env_var("COLORTERM").as_str()) {ColorMode::TrueColor}
+        else if env_var("TERM").ends_with("-256color") {ColorMode::Ansi256}
+        else {ColorMode::Ansi}
+    }
+}
+
+
+
+/// How the colors and attributes of the cells are written to the output
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum ColorMode {
+    Plain,      // Characters only, without any escape code (for pipes, files and terminals without colors)
+    Ansi,       // The 16 colors of the terminal
+    Ansi256,    // The 256 colors of xterm, closest to the palette of Color::rgb
+    TrueColor,  // 24-bit colors, from the palette of Color::rgb
+}
+
+
+
+
+
+
+/// The OpenStreetMap element drawn on a cell: its type (node, way or relation) and its id
+pub type FeatureRef = (MemberType, u64);
+
+
+
+/// A cell of the map: a character with its colors and attributes, the priority it was drawn with,
+/// and the element it represents
+#[derive(Copy, Clone, PartialEq)]
+pub struct Cell {
+    pub glyph: char,
+    pub fg: Option<Color>,              // Color of the character, None for the default color of the terminal
+    pub bg: Option<Color>,              // Color of the background, None for the default color of the terminal
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub priority: u8,                   // Draw priority: the cell is only drawn over by a cell of the same or a higher priority
+    pub feature_ref: Option<FeatureRef>,
+}
+
+
+impl Cell {
+
+    /// Return a cell of the given character, with the default colors and the lowest priority
+    pub fn new(glyph: char) -> Cell {
+        Cell {
+            glyph,
+            fg: Option::None,
+            bg: Option::None,
+            bold: false,
+            dim: false,
+            italic: false,
+            priority: 0,
+            feature_ref: Option::None,
+        }
+    }
+
+
+
+    /// Return a cell of the given character and color, drawn with the given priority
+    pub fn from(glyph: char, fg: Option<Color>, priority: u8) -> Cell {
+        Cell {fg, priority, ..Cell::new(glyph)}
+    }
+
+
+
+    /// Return a blank cell, with the default colors and the lowest priority
+    pub fn blank() -> Cell {
+        Cell::new(' ')
+    }
+
+
+
+    /// Return this cell, representing the given OpenStreetMap element
+    pub fn with_feature(mut self, feature_ref: FeatureRef) -> Cell {
+        self.feature_ref = Some(feature_ref);
+        self
+    }
+
+
+
+    /// Return the cell covered by the right half of the wide character of this cell (see to_line)
+    pub fn continuation(&self) -> Cell {
+        Cell {glyph: CONTINUATION, ..*self}
+    }
+
+
+
+    /// Replace this cell by the given one, unless this one has a higher priority
+    pub fn draw(&mut self, cell: Cell) {
+        if cell.priority >= self.priority {*self = cell;}
+    }
+
+
+
+    /// Return true if the character of this cell is displayed with the same colors and attributes as the other one
+    fn has_style_of(&self, other: &Cell) -> bool {
+        self.fg == other.fg && self.bg == other.bg && self.bold == other.bold && self.dim == other.dim && self.italic == other.italic
+    }
+
+
+
+    /// Return the ansi escape code displaying the colors and attributes of this cell in the given mode,
+    /// or "" if it has the default ones or if the mode is plain
+    fn ansi_style(&self, color_mode: ColorMode) -> String {
+        if color_mode == ColorMode::Plain {return String::new();}
+
+        let mut codes: Vec<String> = Vec::new();
+        if self.bold {codes.push(String::from("1"));}
+        if self.dim {codes.push(String::from("2"));}
+        if self.italic {codes.push(String::from("3"));}
+        if let Some(fg) = self.fg {codes.push(fg.ansi_params(color_mode, false));}
+        if let Some(bg) = self.bg {codes.push(bg.ansi_params(color_mode, true));}
+
+        if codes.is_empty() {String::new()}
+        else {format!("\x1b[{}m", codes.join(";"))}
+    }
+}
+
+
+
+
+
+
+/// Return the characters of the given text with their width in columns of the terminal (1, or 2 for the wide ones),
+/// leaving out the zero-width ones, which have no cell of their own
+pub fn display_chars(text: &str) -> Vec<(char, usize)> {
+    text.chars().filter_map(|c| c.width().filter(|width| *width > 0).map(|width| (c, width))).collect()
+}
+
+
+
+/// Return the text displaying the given cells, with the ansi escape codes of their colors and attributes in the given mode.
+/// A code is only written when the style changes, and the style is reset at the end of the text.
+/// A wide character is written over its cell and the continuation cell after it; when one of them was drawn over,
+/// the other one is written as a space, so the line keeps one column per cell.
+pub fn to_line(cells: &[Cell], color_mode: ColorMode) -> String {
+    let mut line = String::new();
+    let mut current = Cell::blank();
+
+    for (i, cell) in cells.iter().enumerate() {
+        let is_wide = |cell: Option<&Cell>| cell.and_then(|cell| cell.glyph.width()) == Some(2);
+        let glyph = match cell.glyph {
+            CONTINUATION if is_wide(i.checked_sub(1).and_then(|j| cells.get(j))) => continue,
+            CONTINUATION => ' ',
+            _ if is_wide(Some(cell)) && cells.get(i + 1).map(|next| next.glyph) != Some(CONTINUATION) => ' ',
+            glyph => glyph,
+        };
+
+        if !cell.has_style_of(&current) {
+            if !current.ansi_style(color_mode).is_empty() {line.push_str(RESET);}
+            line.push_str(&cell.ansi_style(color_mode));
+            current = *cell;
+        }
+        line.push(glyph);
+    }
+
+    if !current.ansi_style(color_mode).is_empty() {line.push_str(RESET);}
+    line
+}
+
+
+
+// Ansi escape code resetting the colors and attributes
+const RESET: &str = "\x1b[0m";
+
+// Character of the cell covered by the right half of the wide character of the cell on its left
+const CONTINUATION: char = '\0';
\ No newline at end of file
@@ -0,0 +1,79 @@
+use crate::error::Error;
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use toml::value::Table;
+
+
+
+/// Name of the directory of Rustreet in the user configuration directory
+static CONFIG_DIR_NAME: &str = "rustreet";
+
+/// Name of the configuration file, in the configuration directory
+static CONFIG_FILE_NAME: &str = "config.toml";
+
+
+
+
+
+
+/// Options read from the configuration file. The arguments of the command line override them.
+///
+/// Example of configuration file:
+/// ```toml
+/// theme = "dark"
+/// ```
+pub struct Config {
+    pub theme: Option<String>,  // Name of the theme, or path of a theme file relative to the configuration directory (see theme::Theme::load)
+}
+
+
+impl Config {
+
+    /// Return the configuration read from the configuration file ($XDG_CONFIG_HOME/rustreet/config.toml on Linux).
+    /// Every option is unset if there is no configuration file.
+    pub fn load() -> Result<Config, Error> {
+        let mut config = Config {theme: Option::None};
+
+        let path = match config_dir() {
+            Some(dir) => dir.join(CONFIG_FILE_NAME),
+            None => return Ok(config),
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(config),
+            Err(err) => return Err(Error::Io(err)),
+        };
+
+        for (key, value) in parse_toml(&text, &path.display().to_string())? {
+            match key.as_str() {
+                "theme" => match value.as_str() {
+                    Some(theme) => config.theme = Some(theme.to_string()),
+                    None => return Err(Error::MalformedConfig(format!("{}: the theme must be a string", path.display()))),
+                },
+                _ => return Err(Error::MalformedConfig(format!("{}: unknown option '{}'", path.display(), key))),
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+
+
+
+
+
+/// Return the configuration directory of Rustreet ($XDG_CONFIG_HOME/rustreet on Linux),
+/// or None if the user has no configuration directory
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(CONFIG_DIR_NAME))
+}
+
+
+
+/// Return the table of the given TOML text, read from the given source (a file name), used in the error messages
+pub fn parse_toml(text: &str, source: &str) -> Result<Table, Error> {
+    toml::from_str::<Table>(text).map_err(|err| Error::MalformedConfig(format!("{}: {}", source, err)))
+}
\ No newline at end of file
@@ -0,0 +1,82 @@
+use std::fmt;
+use std::io;
+
+
+
+/// Errors that can happen while requesting, parsing or displaying the map data
+#[derive(Debug)]
+pub enum Error {
+    Network(reqwest::Error),    // The request could not be sent or its response could not be read
+    HttpStatus(u16),            // The server answered with an error status
+    RateLimited,                // The server refused the request because too many were sent
+    NoResult(String),           // Nominatim did not find any place matching the search string
+    MalformedJson(String),      // The response is not valid JSON or lacks required fields
+    MalformedFile(String),      // The input file is not a valid OpenStreetMap file
+    MalformedConfig(String),    // The configuration file or a theme is not valid, or the theme doesn't exist
+    OverpassRemark(String),     // Overpass could not complete the query (timeout, out of memory, etc.)
+    Io(io::Error),              // Error while reading or writing to the terminal or a file
+    NoEndpoint,                 // No endpoint was given to send the requests to
+}
+
+
+impl Error {
+
+    /// Return the exit code of the program when it stops because of this error.
+    /// The codes follow the convention of sysexits.h.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::NoResult(_) => 1,
+            Error::MalformedJson(_) => 65,      // EX_DATAERR
+            Error::MalformedFile(_) => 65,      // EX_DATAERR
+            Error::Network(_) => 69,            // EX_UNAVAILABLE
+            Error::HttpStatus(_) => 69,         // EX_UNAVAILABLE
+            Error::Io(_) => 74,                 // EX_IOERR
+            Error::RateLimited => 75,           // EX_TEMPFAIL
+            Error::OverpassRemark(_) => 75,     // EX_TEMPFAIL
+            Error::NoEndpoint => 78,            // EX_CONFIG
+            Error::MalformedConfig(_) => 78,    // EX_CONFIG
+        }
+    }
+}
+
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Network(err) => write!(f, "Network error: {}", err),
+            Error::HttpStatus(status) => write!(f, "The server answered with the HTTP status {}", status),
+            Error::RateLimited => write!(f, "Too many requests were sent to the server. Please try again later"),
+            Error::NoResult(search) => write!(f, "No place found for '{}'", search),
+            Error::MalformedJson(details) => write!(f, "Invalid response from the server: {}", details),
+            Error::MalformedFile(details) => write!(f, "Invalid input file: {}", details),
+            Error::MalformedConfig(details) => write!(f, "Invalid configuration: {}", details),
+            Error::OverpassRemark(remark) => write!(f, "Overpass could not complete the request: {}", remark),
+            Error::Io(err) => write!(f, "{}", err),
+            Error::NoEndpoint => write!(f, "No API endpoint to send the request to"),
+        }
+    }
+}
+
+
+impl std::error::Error for Error {}
+
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Error {
+        Error::Network(err)
+    }
+}
+
+
+impl From<json::Error> for Error {
+    fn from(err: json::Error) -> Error {
+        Error::MalformedJson(err.to_string())
+    }
+}
+
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
\ No newline at end of file
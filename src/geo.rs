@@ -1,22 +1,41 @@
 use std::f64::consts::PI;
 
-/// Represent a bounding box (a square on the map)
+
+
+// Length of a degree of latitude, and of a degree of longitude at the equator (in km)
+const KM_PER_LAT_DEGREE: f64 = 110.574;
+const KM_PER_LON_DEGREE: f64 = 111.320;
+
+// Equatorial radius (in km) and flattening of the WGS 84 ellipsoid
+const WGS84_RADIUS: f64 = 6378.137;
+const WGS84_FLATTENING: f64 = 1.0 / 298.257223563;
+
+// Latitude limit of the Web Mercator projection (the map is a square)
+const MAX_MERCATOR_LAT: f64 = 85.051129;
+
+// Scale factor on the central meridian of a UTM zone
+const UTM_SCALE: f64 = 0.9996;
+
+
+
+/// Represent a bounding box (a rectangle on the map, whose width and height in kilometers follow an aspect ratio)
 #[derive(Copy, Clone)]
 pub struct BoundingBox {
     pub coo: [f64; 4], // [min_lat, min_lon, max_lat, max_lon]
     pub dim_deg: [f64; 2], // [delta lat, delta lon] (basically, width and height)
     pub dim_km: [f64; 2], // [delta lat in km, delta lon in km] (basically, width and height)
+    aspect_ratio: f64, // Width divided by height, in kilometers
 }
 
 impl BoundingBox {
 
-    /// Return a new bounding box
+    /// Return a new bounding box, expanded to a square
     pub fn new(min_lat: f64, min_lon: f64, max_lat: f64, max_lon: f64) -> BoundingBox {
         let coo = [min_lat, min_lon, max_lat, max_lon];
 
-        let mut bounding_box = BoundingBox{coo, dim_deg: [0.0, 0.0], dim_km: [0.0, 0.0]};
+        let mut bounding_box = BoundingBox{coo, dim_deg: [0.0, 0.0], dim_km: [0.0, 0.0], aspect_ratio: 1.0};
         bounding_box.compute_size();
-        bounding_box.resize();
+        bounding_box.set_aspect_ratio(1.0);
 
         bounding_box
     }
@@ -28,28 +47,48 @@ impl BoundingBox {
         self.dim_deg[0] = self.coo[2] - self.coo[0];
         self.dim_deg[1] = self.coo[3] - self.coo[1];
         
-        self.dim_km = lat_lon_to_km(self.dim_deg);
+        self.dim_km = lat_lon_to_km(self.dim_deg, self.center()[0]);
     }
 
 
-    /// Resize this bounding box to represent a square. The size of the square is the smallest side of the bounding box.
-    /// The bounding box is resize accordingly to its size in kilometers, not in lon/lat degrees.
-    pub fn resize(&mut self) {
 
-        // Compute the new size of the bounding box
-        let new_dim_km = [self.dim_km[0].min(self.dim_km[1]), self.dim_km[0].min(self.dim_km[1])];
+    /// Return the (lat, lon) coordinates of the center of this bounding box
+    pub fn center(&self) -> [f64; 2] {
+        [(self.coo[0] + self.coo[2]) / 2.0, (self.coo[1] + self.coo[3]) / 2.0]
+    }
+
+
+    /// Change the aspect ratio (width / height, in kilometers) of this bounding box. The box is expanded around
+    /// its center, so the area it covered stays entirely inside of it.
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f64) {
+        self.aspect_ratio = aspect_ratio;
+
+        // Too wide: increase the height. Too high: increase the width (in resize).
+        if self.dim_km[1] > self.dim_km[0] * aspect_ratio {
+            let new_d_lat = km_to_lat_lon([self.dim_km[1] / aspect_ratio, 0.0], self.center()[0])[0];
+            let lat_difference = (new_d_lat - self.dim_deg[0]) / 2.0;
+
+            self.coo[0] -= lat_difference;
+            self.coo[2] += lat_difference;
+            self.compute_size();
+        }
+
+        self.resize();
+    }
+
+
+    /// Resize this bounding box to follow its aspect ratio. The height is kept, and the width is changed
+    /// accordingly to its size in kilometers, not in lon/lat degrees.
+    pub fn resize(&mut self) {
 
-        // Compute the new bounding box in degrees
-        let new_dim_lat_lon = km_to_lat_lon(new_dim_km);
+        // Compute the new width of the bounding box in degrees
+        let new_d_lon = km_to_lat_lon([0.0, self.dim_km[0] * self.aspect_ratio], self.center()[0])[1];
 
         // Compute the new coordinates of the bounding box
-        let delta_width = (self.dim_deg[0] - new_dim_lat_lon[0]) / 2.0;
-        let delta_height = (self.dim_deg[1] - new_dim_lat_lon[1]) / 2.0;
+        let lon_difference = (new_d_lon - self.dim_deg[1]) / 2.0;
 
-        self.coo[0] += delta_width;
-        self.coo[2] -= delta_width;
-        self.coo[1] += delta_height;
-        self.coo[3] -= delta_height;
+        self.coo[1] -= lon_difference;
+        self.coo[3] += lon_difference;
 
         // Compte new size
         self.compute_size();
@@ -59,16 +98,16 @@ impl BoundingBox {
     /// Modify the bounding box to zoom in or out by a given factor.
     /// A factor > 1 will zoom in, a factor < 1 will zoom out.
     pub fn zoom(&mut self, factor: f64) {
-        let new_width = self.dim_deg[0] / factor;
-        let new_height = self.dim_deg[1] / factor;
+        let new_d_lat = self.dim_deg[0] / factor;
+        let new_d_lon = self.dim_deg[1] / factor;
 
-        let width_difference = (self.dim_deg[0] - new_width) / 2.0;
-        let height_difference = (self.dim_deg[1] - new_height) / 2.0;
+        let lat_difference = (self.dim_deg[0] - new_d_lat) / 2.0;
+        let lon_difference = (self.dim_deg[1] - new_d_lon) / 2.0;
 
-        self.coo[0] += height_difference;
-        self.coo[2] -= height_difference;
-        self.coo[1] += width_difference;
-        self.coo[3] -= width_difference;
+        self.coo[0] += lat_difference;
+        self.coo[2] -= lat_difference;
+        self.coo[1] += lon_difference;
+        self.coo[3] -= lon_difference;
 
         self.resize();
     }
@@ -84,33 +123,175 @@ impl BoundingBox {
 
         self.resize();
     }
+
+
+    /// Return true if the given bounding box is entirely contained in this one
+    pub fn contains(&self, other: &BoundingBox) -> bool {
+        other.coo[0] >= self.coo[0] && other.coo[1] >= self.coo[1] && other.coo[2] <= self.coo[2] && other.coo[3] <= self.coo[3]
+    }
+
+
+    /// Return true if the given (lat, lon) point is inside this bounding box
+    pub fn contains_point(&self, point: [f64; 2]) -> bool {
+        point[0] >= self.coo[0] && point[1] >= self.coo[1] && point[0] <= self.coo[2] && point[1] <= self.coo[3]
+    }
 }
 
 
 
 
-/// Convert a vector (lat, lon) in degrees to a vector (lat, lon) in kilometers
-fn lat_lon_to_km(vector: [f64; 2]) -> [f64; 2] {
+/// Convert a vector (lat, lon) in degrees to a vector (lat, lon) in kilometers, around the given latitude
+fn lat_lon_to_km(vector: [f64; 2], latitude: f64) -> [f64; 2] {
     let lat_deg = vector[0];
     let lon_deg = vector[1];
 
-    // Convert given latitude to radians
-    let lat_rad = lat_deg * PI / 180.0;
-
-    // Convert considering the earth like a perfect sphere
-    [lat_deg * 110.574, lon_deg * 111.320 * lat_rad.cos()]
+    // Convert considering the earth like a perfect sphere: the length of a degree of longitude
+    // depends on the latitude it is measured at
+    [lat_deg * KM_PER_LAT_DEGREE, lon_deg * KM_PER_LON_DEGREE * latitude.to_radians().cos()]
 }
 
 
-/// Convert a vector (lat, lon) in kilometers to a vector (lat, lon) in degrees
-fn km_to_lat_lon(vector: [f64; 2]) -> [f64; 2] {
+/// Convert a vector (lat, lon) in kilometers to a vector (lat, lon) in degrees, around the given latitude
+fn km_to_lat_lon(vector: [f64; 2], latitude: f64) -> [f64; 2] {
     let lat_km = vector[0];
     let lon_km = vector[1];
 
-    // Convert given latitude to degrees and radians
-    let lat_deg = lat_km / 110.574;
-    let lat_rad = lat_deg * PI / 180.0;
-
     // Convert considering the earth like a perfect sphere
-    [lat_deg, lon_km / 111.320 / lat_rad.cos()]
+    [lat_km / KM_PER_LAT_DEGREE, lon_km / KM_PER_LON_DEGREE / latitude.to_radians().cos()]
+}
+
+
+
+
+
+
+/// A map projection: the way the (lat, lon) coordinates of the earth are placed on the flat map
+#[derive(clap::ArgEnum, Copy, Clone, PartialEq, Eq)]
+pub enum Projection {
+    WebMercator,        // Spherical Mercator, as used by openstreetmap.org and most web maps
+    Equirectangular,    // Linear in lat/lon, with the longitudes scaled at the latitude of the center of the map
+    Utm,                // Universal Transverse Mercator, in the zone of the center of the map
+}
+
+
+impl Projection {
+
+    /// Return the (northing, easting) coordinates in kilometers of the given (lat, lon) point, for a map
+    /// centered on the given (lat, lon) point. The coordinates are only meaningful relative to each other.
+    pub fn project(&self, point: [f64; 2], center: [f64; 2]) -> [f64; 2] {
+        match self {
+            Projection::WebMercator => {
+                let lat_rad = point[0].clamp(-MAX_MERCATOR_LAT, MAX_MERCATOR_LAT).to_radians();
+                [WGS84_RADIUS * (PI / 4.0 + lat_rad / 2.0).tan().ln(), WGS84_RADIUS * point[1].to_radians()]
+            },
+
+            Projection::Equirectangular => lat_lon_to_km(point, center[0]),
+
+            Projection::Utm => utm(point, center),
+        }
+    }
+}
+
+
+
+
+/// Return the (northing, easting) UTM coordinates in kilometers of the given (lat, lon) point, in the zone
+/// of the given center point (Snyder, Map Projections - A Working Manual, formulas 8-9 to 8-13)
+fn utm(point: [f64; 2], center: [f64; 2]) -> [f64; 2] {
+    let zone = ((center[1] + 180.0) / 6.0).floor().clamp(0.0, 59.0);
+    let central_meridian = zone * 6.0 - 180.0 + 3.0;
+
+    let e2 = WGS84_FLATTENING * (2.0 - WGS84_FLATTENING);   // First eccentricity, squared
+    let ep2 = e2 / (1.0 - e2);                              // Second eccentricity, squared
+    let (e4, e6) = (e2 * e2, e2 * e2 * e2);
+
+    let lat = point[0].to_radians();
+    let n = WGS84_RADIUS / (1.0 - e2 * lat.sin().powi(2)).sqrt();
+    let t = lat.tan().powi(2);
+    let c = ep2 * lat.cos().powi(2);
+    let a = lat.cos() * (point[1] - central_meridian).to_radians();
+
+    // Distance along the central meridian from the equator
+    let m = WGS84_RADIUS * (
+        (1.0 - e2 / 4.0 - 3.0 * e4 / 64.0 - 5.0 * e6 / 256.0) * lat
+        - (3.0 * e2 / 8.0 + 3.0 * e4 / 32.0 + 45.0 * e6 / 1024.0) * (2.0 * lat).sin()
+        + (15.0 * e4 / 256.0 + 45.0 * e6 / 1024.0) * (4.0 * lat).sin()
+        - (35.0 * e6 / 3072.0) * (6.0 * lat).sin()
+    );
+
+    let easting = UTM_SCALE * n * (a + (1.0 - t + c) * a.powi(3) / 6.0 + (5.0 - 18.0 * t + t * t + 72.0 * c - 58.0 * ep2) * a.powi(5) / 120.0);
+    let northing = UTM_SCALE * (m + n * lat.tan() * (
+        a * a / 2.0
+        + (5.0 - t + 9.0 * c + 4.0 * c * c) * a.powi(4) / 24.0
+        + (61.0 - 58.0 * t + t * t + 600.0 * c - 330.0 * ep2) * a.powi(6) / 720.0
+    ));
+
+    // False easting and northing (in the southern hemisphere) of the UTM grid
+    let false_northing = if center[0] < 0.0 {10000.0} else {0.0};
+    [northing + false_northing, easting + 500.0]
+}
+
+
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    fn assert_close(value: f64, expected: f64, tolerance: f64) {
+        assert!((value - expected).abs() <= tolerance, "{} != {}", value, expected);
+    }
+
+
+
+    #[test]
+    fn web_mercator_is_square_at_its_latitude_limit() {
+        // The world is a square of 2 x 20037.508 km between the latitudes of ±85.0511°
+        let top = Projection::WebMercator.project([85.0511287798, 180.0], [0.0, 0.0]);
+        assert_close(top[0], 20037.508343, 1e-3);
+        assert_close(top[1], 20037.508343, 1e-6);
+
+        let bottom = Projection::WebMercator.project([-85.0511287798, -180.0], [0.0, 0.0]);
+        assert_close(bottom[0], -20037.508343, 1e-3);
+        assert_close(bottom[1], -20037.508343, 1e-6);
+
+        // Beyond the limit, the points are on the border of the square
+        assert_eq!(Projection::WebMercator.project([89.0, 0.0], [0.0, 0.0])[0], Projection::WebMercator.project([85.1, 0.0], [0.0, 0.0])[0]);
+    }
+
+
+
+    #[test]
+    fn utm_matches_reference_points() {
+        // The CN Tower, in Toronto, is at 630084 m east and 4833438 m north in the zone 17
+        let cn_tower = Projection::Utm.project([43.642567, -79.387139], [43.6, -79.4]);
+        assert_close(cn_tower[1], 630.084, 2e-3);
+        assert_close(cn_tower[0], 4833.438, 2e-3);
+
+        // The central meridian of a zone is at 500 km east; the southern hemisphere starts at 10000 km north
+        let central = Projection::Utm.project([-30.0, 3.0], [-30.0, 3.0]);
+        assert_close(central[1], 500.0, 1e-9);
+        let north = Projection::Utm.project([30.0, 5.0], [30.0, 5.0]);
+        let south = Projection::Utm.project([-30.0, 5.0], [-30.0, 5.0]);
+        assert_close(south[0], 10000.0 - north[0], 1e-9);
+        assert_close(south[1], north[1], 1e-9);
+    }
+
+
+
+    #[test]
+    fn equirectangular_scales_longitudes_at_the_center_latitude() {
+        let origin = Projection::Equirectangular.project([0.0, 0.0], [60.0, 10.0]);
+        let point = Projection::Equirectangular.project([1.0, 1.0], [60.0, 10.0]);
+
+        assert_close(point[0] - origin[0], KM_PER_LAT_DEGREE, 1e-9);
+        assert_close(point[1] - origin[1], KM_PER_LON_DEGREE * 0.5, 1e-9);
+
+        // The scale doesn't depend on the latitude of the point
+        let far = Projection::Equirectangular.project([50.0, 1.0], [60.0, 10.0]);
+        assert_close(far[1] - origin[1], KM_PER_LON_DEGREE * 0.5, 1e-9);
+    }
 }
\ No newline at end of file
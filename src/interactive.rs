@@ -0,0 +1,267 @@
+use crate::Args;
+use crate::error::Error;
+use crate::geo::BoundingBox;
+use crate::map::{self, MetaMap};
+use crate::osm::DataSource;
+use crate::theme::Theme;
+
+use std::io::{self, Write};
+use crossterm::{cursor, execute, queue, terminal};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::style::Print;
+
+
+
+/// Fraction of the displayed area the view is moved by at each key press
+const PAN_STEP: f64 = 0.25;
+
+/// Zoom factor applied to the view when zooming in (and its inverse when zooming out)
+const ZOOM_STEP: f64 = 1.5;
+
+/// Zoom factor applied to the displayed area to get the area requested to Overpass.
+/// A factor of 0.5 requests an area twice as large as the view, so the user can move
+/// a bit before new data is needed.
+const FETCH_ZOOM: f64 = 0.5;
+
+/// Help message displayed under the map
+const HELP_MSG: &str = "[arrows/hjkl] move   [+/-] zoom   [q] quit";
+
+
+
+
+
+
+/// Put the terminal in raw mode and on the alternate screen as long as it lives.
+/// The terminal is restored when dropped, even if the program panics.
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn new() -> io::Result<TerminalGuard> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(TerminalGuard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+
+
+
+
+
+/// Action requested by the user with a key press
+enum Action {
+    Move(f64, f64), // [lat, lon] direction
+    Zoom(f64),      // zoom factor
+    Quit,
+    Nothing,
+}
+
+
+impl Action {
+
+    /// Return the action corresponding to the given key event
+    fn from(key: KeyEvent) -> Action {
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => Action::Move(1.0, 0.0),
+            KeyCode::Down | KeyCode::Char('j') => Action::Move(-1.0, 0.0),
+            KeyCode::Left | KeyCode::Char('h') => Action::Move(0.0, -1.0),
+            KeyCode::Right | KeyCode::Char('l') => Action::Move(0.0, 1.0),
+
+            KeyCode::Char('+') | KeyCode::Char('=') => Action::Zoom(ZOOM_STEP),
+            KeyCode::Char('-') => Action::Zoom(1.0 / ZOOM_STEP),
+
+            KeyCode::Char('q') | KeyCode::Esc => Action::Quit,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Action::Quit,
+
+            _ => Action::Nothing,
+        }
+    }
+}
+
+
+
+
+
+
+/// State of an interactive session: the displayed area, the area whose data is loaded and the map itself
+struct Session<'a> {
+    args: &'a Args,                 // Options of the map
+    theme: Theme,                   // Characters and colors of the map
+    source: Box<dyn DataSource>,
+    view: BoundingBox,              // Area currently displayed
+    fetched: BoundingBox,           // Area whose data is loaded in the map
+    fetched_lvl: u8,                // Level of details of the loaded data
+    map: MetaMap,
+    size: [u16; 2],                 // Height and width of the map, in characters
+}
+
+
+impl<'a> Session<'a> {
+
+    /// Create a new session displaying the given area in the given theme, and request its data
+    fn new(view: BoundingBox, mut source: Box<dyn DataSource>, size: [u16; 2], args: &'a Args, theme: Theme) -> Result<Session<'a>, Error> {
+
+        let fetched = Session::fetch_box(&view);
+        let fetched_lvl = crate::detail_lvl(args, &view);
+        let data = source.load(fetched, &crate::element_filter(args, fetched_lvl))?;
+        let map = crate::build_map(args, &theme, data, view, size, fetched_lvl);
+
+        Ok(Session {args, theme, source, view, fetched, fetched_lvl, map, size})
+    }
+
+
+
+    /// Return the area whose data must be loaded in order to display the given view
+    fn fetch_box(view: &BoundingBox) -> BoundingBox {
+        let mut fetch_box = *view;
+        fetch_box.zoom(FETCH_ZOOM);
+        fetch_box
+    }
+
+
+
+    /// Return true if the data of the current view is not loaded yet: either the view left
+    /// the loaded area, it requires more detailed ways than the loaded ones, or boundaries of other admin levels
+    fn needs_fetch(&self) -> bool {
+        let view_lvl = crate::detail_lvl(self.args, &self.view);
+        let fetched_admin_levels = crate::element_filter(self.args, self.fetched_lvl).admin_levels;
+        let has_boundaries = crate::element_filter(self.args, view_lvl).admin_levels.iter().all(|admin_level| fetched_admin_levels.contains(admin_level));
+
+        !self.fetched.contains(&self.view) || view_lvl > self.fetched_lvl || !has_boundaries
+    }
+
+
+
+    /// Load the data around the current view and replace the map with it.
+    /// If the request fails, the current map is kept.
+    fn fetch(&mut self) -> Result<(), Error> {
+        let fetched = Session::fetch_box(&self.view);
+        let fetched_lvl = crate::detail_lvl(self.args, &self.view);
+        let data = self.source.load(fetched, &crate::element_filter(self.args, fetched_lvl))?;
+
+        self.map = crate::build_map(self.args, &self.theme, data, self.view, self.size, fetched_lvl);
+        self.fetched = fetched;
+        self.fetched_lvl = fetched_lvl;
+        Ok(())
+    }
+
+
+
+    /// Move the view in the given direction, by a fraction of its size
+    fn move_view(&mut self, d_lat: f64, d_lon: f64) {
+        self.view.translate([d_lat * self.view.dim_deg[0] * PAN_STEP, d_lon * self.view.dim_deg[1] * PAN_STEP]);
+        self.map.set_display_box(self.view);
+    }
+
+
+
+    /// Zoom the view in or out by the given factor. When zooming out inside the loaded area,
+    /// the ways too detailed for the new view are hidden instead of requesting new data.
+    fn zoom_view(&mut self, factor: f64) {
+        self.view.zoom(factor);
+        self.map.set_display_box(self.view);
+        self.map.set_detail_lvl(crate::detail_lvl(self.args, &self.view));
+    }
+
+
+
+    /// Change the size of the map (in characters). The view is expanded to the new shape of the map.
+    fn resize(&mut self, size: [u16; 2]) {
+        self.size = size;
+        self.view.set_aspect_ratio(map::display_ratio(size[0], size[1]));
+
+        self.map.display_height = size[0];
+        self.map.display_width = size[1];
+        self.map.set_display_box(self.view);
+    }
+
+
+
+    /// Draw the map and the given status message on the terminal
+    fn draw(&self, status: &str) -> io::Result<()> {
+        let mut stdout = io::stdout();
+
+        let mut ascii_map = self.map.generate_ascii_map().with_decoration();
+        ascii_map.set_color_mode(self.args.color.color_mode());
+        let mut lines = ascii_map.lines();
+        lines.push(String::from(status));
+
+        queue!(stdout, terminal::Clear(terminal::ClearType::All))?;
+        for (i, line) in lines.iter().enumerate() {
+            queue!(stdout, cursor::MoveTo(0, i as u16), Print(line))?;
+        }
+
+        stdout.flush()
+    }
+}
+
+
+
+
+
+
+/// Run Rustreet in interactive mode: display the map on the whole terminal and let the user
+/// move it with the keyboard, until 'q' is pressed.
+pub fn run(args: &Args) -> Result<(), Error> {
+
+    // Request bounding box from Nominatim, or read it from the input file
+    let disk_cache = crate::disk_cache(args);
+    let mut source = crate::data_source(args, disk_cache.clone())?;
+    let mut view = crate::initial_box(args, source.as_mut(), disk_cache)?;
+    let theme = crate::theme(args)?;
+
+    let _guard = TerminalGuard::new()?;
+
+    // Expand the area to the shape of the map, then apply zoom
+    let size = crate::map_size(args);
+    view.set_aspect_ratio(map::display_ratio(size[0], size[1]));
+    view.zoom(args.zoom);
+
+    let mut session = Session::new(view, source, size, args, theme)?;
+    session.draw(HELP_MSG)?;
+
+    // Message displayed under the map until the next key press
+    let mut status = String::from(HELP_MSG);
+
+    loop {
+        match event::read()? {
+            Event::Key(key) => {
+                status = String::from(HELP_MSG);
+
+                match Action::from(key) {
+                    Action::Quit => break,
+                    Action::Nothing => continue,
+
+                    Action::Move(d_lat, d_lon) => session.move_view(d_lat, d_lon),
+                    Action::Zoom(factor) => session.zoom_view(factor),
+                }
+            },
+
+            // Follow the size of the terminal, unless the size of the map is given by the arguments
+            Event::Resize(_, _) => session.resize(crate::map_size(args)),
+
+            _ => continue,
+        }
+
+        // Request new data only when the loaded one is not enough to display the view
+        if session.needs_fetch() {
+            session.draw("Loading map data...")?;
+
+            // Errors are displayed instead of stopping the session, so the user can try again
+            if let Err(err) = session.fetch() {
+                status = format!("[ERROR] {}", err);
+            }
+        }
+
+        session.draw(&status)?;
+    }
+
+    Ok(())
+}
\ No newline at end of file
@@ -3,8 +3,29 @@ mod geo;
 mod map;
 mod ascii_map;
 mod style;
+mod cell;
+mod theme;
+mod config;
+mod interactive;
+mod cache;
+mod error;
+mod osm;
+mod pbf;
+mod osm_file;
+mod raster;
 
 use clap::Parser;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+
+
+// Height of the map when the size of the terminal is unknown (in characters)
+const DEFAULT_SIZE: u16 = 60;
+
+// Rows and columns of the terminal taken by the decoration of the map
+const RESERVED_ROWS: u16 = 4;
+const RESERVED_COLUMNS: u16 = 2;
 
 
 
@@ -22,10 +43,18 @@ fn check_arguments(args: &Args) {
         err.exit();
     }
 
+    if args.details_lvl.is_some_and(|lvl| lvl > style::MAX_DETAIL_LVL) {
+        let err = app.error(clap::ErrorKind::InvalidValue, format!("The level of details must be between 0 and {}.", style::MAX_DETAIL_LVL));
+        err.exit();
+    }
+
+    if [args.size, args.width, args.height].contains(&Some(0)) {
+        let err = app.error(clap::ErrorKind::InvalidValue, "The size of the map must be greater than 0.");
+        err.exit();
+    }
 
-    // TODO: REMOVE WHEN INTERACTIVE MODE IS IMPLEMENTED
-    if args.interactive {
-        let err = app.error(clap::ErrorKind::ArgumentConflict, "Interactive mode not implemented yet.");
+    if args.search.is_none() && args.input.is_none() {
+        let err = app.error(clap::ErrorKind::MissingRequiredArgument, "A search string is required when no input file is given.");
         err.exit();
     }
 }
@@ -33,33 +62,191 @@ fn check_arguments(args: &Args) {
 
 
 
-/// Process the request of the user one time, print the generated map and exit the program
-fn one_shot(args: &Args) {
+/// Return the (height, width) in characters of the area of the terminal available for the map,
+/// or None if the size of the terminal can't be read (when not running in a terminal)
+fn terminal_map_size() -> Option<[u16; 2]> {
+    let (columns, rows) = crossterm::terminal::size().ok()?;
+
+    // Keep room for the borders, the scale and the prompt (or the status line in interactive mode)
+    let size = [rows.saturating_sub(RESERVED_ROWS), columns.saturating_sub(RESERVED_COLUMNS)];
+    if size.contains(&0) {Option::None} else {Some(size)}
+}
+
+
+/// Return the (height, width) in characters of the map: the given width and height, or the given size
+/// (a map twice as wide as high, to look square), or the size of the terminal.
+fn map_size(args: &Args) -> [u16; 2] {
+    let default_size = match args.size {
+        Some(size) => [size, size.saturating_mul(2)],
+        None => terminal_map_size().unwrap_or([DEFAULT_SIZE, 2 * DEFAULT_SIZE]),
+    };
+
+    [args.height.unwrap_or(default_size[0]), args.width.unwrap_or(default_size[1])]
+}
+
+
+
+
+/// Return the level of details of the ways to display in the given area: the one given by the arguments,
+/// or the one deduced from the size of the area
+fn detail_lvl(args: &Args, bbox: &geo::BoundingBox) -> u8 {
+    args.details_lvl.unwrap_or_else(|| api_wrapper::RequestBuilder::get_lvl_details(bbox.dim_km[0]))
+}
+
+
+/// Return the layers to display: the ones given by the arguments, or every layer
+fn layers(args: &Args) -> Vec<style::Layer> {
+    if args.layers.is_empty() {style::ALL_LAYERS.to_vec()} else {args.layers.clone()}
+}
+
+
+/// Return the classes of points of interest to display: the ones given by the arguments, or every class
+fn poi_classes(args: &Args) -> Vec<style::PoiClass> {
+    if args.poi.is_empty() {style::ALL_POI_CLASSES.to_vec()} else {args.poi.clone()}
+}
+
+
+/// Return the theme of the map: the one given by the arguments, or the one of the configuration file, or the default theme.
+/// The path of a theme file is relative to the current directory in the arguments, and to the configuration directory
+/// in the configuration file.
+fn theme(args: &Args) -> Result<theme::Theme, error::Error> {
+    if let Some(name) = &args.theme {
+        return theme::Theme::load(name, Path::new(""));
+    }
+
+    match config::Config::load()?.theme {
+        Some(name) => theme::Theme::load(&name, &config::config_dir().unwrap_or_default()),
+        None => theme::Theme::load("default", Path::new("")),
+    }
+}
+
+
+/// Return the filter of the elements to load for the given level of details, with the layers given by the arguments
+fn element_filter(args: &Args, detail_lvl: u8) -> style::ElementFilter {
+    style::ElementFilter::new(detail_lvl, &layers(args), &poi_classes(args))
+}
+
+
+/// Build the map of the given data, loaded at the given level of details, with the display options given by the arguments
+/// and the given theme
+fn build_map(args: &Args, theme: &theme::Theme, data: osm::OsmData, bbox: geo::BoundingBox, size: [u16; 2], detail_lvl: u8) -> map::MetaMap {
+    let mut map = map::MetaMap::from(data, bbox, size[0], size[1], theme.clone());
+    map.set_detail_lvl(detail_lvl);
+    map.set_layers(&layers(args));
+    map.set_poi_classes(&poi_classes(args));
+    map.set_charset(args.charset);
+    map.set_projection(args.projection);
+    map
+}
+
+
+
+
+/// Return the disk cache to use, as configured by the arguments
+fn disk_cache(args: &Args) -> cache::DiskCache {
+    if args.no_cache {return cache::DiskCache::disabled();}
+
+    let mut disk_cache = cache::DiskCache::new(args.cache_ttl, args.cache_size);
+    disk_cache.set_refresh(args.refresh);
+    disk_cache
+}
+
+
+
 
-    // Request bounding box from Nominatim
-    if args.info {println!("[INFO] Requesting data from Nominatim API")}
-    let mut searcher = api_wrapper::Searcher::new();
-    let mut bbox = searcher.research(&args.search).unwrap();
-    if args.info {println!("[INFO] Nominatim data received. Bounding box: {}, {}, {}, {} (S/W/N/E)", bbox.coo[0], bbox.coo[1], bbox.coo[2], bbox.coo[3])}
+/// Return a fetcher sending requests to the given endpoints, or to the default ones if none is given
+fn fetcher(args: &Args, endpoints: &[String], default_endpoints: &[&str]) -> api_wrapper::Fetcher {
+    let endpoints = if endpoints.is_empty() {default_endpoints.iter().map(|url| url.to_string()).collect()}
+                    else {endpoints.to_vec()};
 
-    // Apply zoom
+    let mut fetcher = api_wrapper::Fetcher::new(endpoints, args.retries);
+    fetcher.set_base_delay(Duration::from_millis(args.retry_delay));
+    fetcher
+}
+
+
+/// Return the Nominatim client, as configured by the arguments
+fn searcher(args: &Args, disk_cache: cache::DiskCache) -> api_wrapper::Searcher {
+    let fetcher = fetcher(args, &args.nominatim_url, &api_wrapper::NOMINATIM_API_URLS);
+    api_wrapper::Searcher::new(fetcher, disk_cache)
+}
+
+
+/// Return the Overpass client, as configured by the arguments
+fn overpass_data(args: &Args, disk_cache: cache::DiskCache) -> api_wrapper::OverpassData {
+    let fetcher = fetcher(args, &args.overpass_url, &api_wrapper::OVERPASS_API_URLS);
+    let mut overpass_data = api_wrapper::OverpassData::new(fetcher, disk_cache);
+    overpass_data.set_timeout(args.timeout);
+    overpass_data
+}
+
+
+/// Return the source of the map data: the input file if one is given, the Overpass API otherwise.
+/// PBF extracts are recognized by their extension, other files are read as OSM XML or Overpass JSON.
+fn data_source(args: &Args, disk_cache: cache::DiskCache) -> Result<Box<dyn osm::DataSource>, error::Error> {
+    match &args.input {
+        Some(path) if path.extension().is_some_and(|extension| extension == "pbf") => Ok(Box::new(pbf::PbfFile::open(path)?)),
+        Some(path) => Ok(Box::new(osm_file::OsmFile::open(path)?)),
+        None => Ok(Box::new(overpass_data(args, disk_cache))),
+    }
+}
+
+
+/// Return the area to display (before applying the zoom): the result of the search if one is given,
+/// the area covered by the data source otherwise
+fn initial_box(args: &Args, source: &mut dyn osm::DataSource, disk_cache: cache::DiskCache) -> Result<geo::BoundingBox, error::Error> {
+    match &args.search {
+        Some(search) => searcher(args, disk_cache).research(search),
+        None => source.bounds()?.ok_or_else(|| error::Error::MalformedFile(String::from("the input file contains no data"))),
+    }
+}
+
+
+
+
+/// Print the given error in the same style as argument errors, then exit the program with the error's exit code
+fn exit_with_error(err: error::Error) -> ! {
+    let _ = clap::Error::raw(clap::ErrorKind::Io, format!("{}\n", err)).print();
+    std::process::exit(err.exit_code());
+}
+
+
+
+
+/// Process the request of the user one time, print the generated map and exit the program
+fn one_shot(args: &Args) -> Result<(), error::Error> {
+    let theme = theme(args)?;
+    let disk_cache = disk_cache(args);
+    let mut source = data_source(args, disk_cache.clone())?;
+
+    // Request bounding box from Nominatim, or read it from the input file
+    if args.info {println!("[INFO] Searching the area to display")}
+    let mut bbox = initial_box(args, source.as_mut(), disk_cache)?;
+    if args.info {println!("[INFO] Area found. Bounding box: {}, {}, {}, {} (S/W/N/E)", bbox.coo[0], bbox.coo[1], bbox.coo[2], bbox.coo[3])}
+
+    // Expand the area to the shape of the map, then apply zoom
+    let size = map_size(args);
+    bbox.set_aspect_ratio(map::display_ratio(size[0], size[1]));
     bbox.zoom(args.zoom);
     if args.info && args.zoom != 0.0 {println!("[INFO] Applied a x{} zoom. New bounding box: {}, {}, {}, {} (S/W/N/E)", args.zoom, bbox.coo[0], bbox.coo[1], bbox.coo[2], bbox.coo[3])}
 
-    // Request map data from the Overpass API
-    if args.info {println!("[INFO] Requesting map data from Overpass API")}
-    let mut overpass_data = api_wrapper::OverpassData::new();
-    let data = overpass_data.request(bbox, args.timeout);
-    if args.info {println!("[INFO] Data received")}
+    // Load map data from the Overpass API or the input file
+    if args.info {println!("[INFO] Loading map data")}
+    let detail_lvl = detail_lvl(args, &bbox);
+    let data = source.load(bbox, &element_filter(args, detail_lvl))?;
+    if args.info {println!("[INFO] Data loaded")}
 
     // Generate the map
-    if args.info {println!("[INFO] Generating map of size {}", args.size)}
-    let map = map::MetaMap::from(data, bbox, args.size);
+    if args.info {println!("[INFO] Generating map of size {}x{}", size[1], size[0])}
+    let map = build_map(args, &theme, data, bbox, size, detail_lvl);
     if args.info {println!("[INFO] Map generated")}
 
     // Display map and exit
-    let ascii_map = map.generate_ascii_map().with_decoration();
+    let mut ascii_map = map.generate_ascii_map().with_decoration();
+    ascii_map.set_color_mode(args.color.color_mode());
     ascii_map.print();
+
+    Ok(())
 }
 
 
@@ -80,20 +267,66 @@ struct Args {
     interactive: bool,
 
     /// A string representing a place. Example: Paris, France
-    search: String,
+    /// Optional when an input file is given: the whole area of the file is displayed.
+    search: Option<String>,
+
+    /// Read the map data from a file instead of the Overpass API: an OpenStreetMap PBF extract (.osm.pbf),
+    /// an OSM XML file (.osm) or an Overpass JSON response (.json).
+    #[clap(long, parse(from_os_str))]
+    input: Option<PathBuf>,
 
     /// The level of zoom, should be greater than 0.
     #[clap(short, long, default_value_t = 1.0)]
     zoom: f64,
 
-    /// Specifies the size of the outputted image.
-    #[clap(short, long, default_value_t = 60)]
-    size: u16,
+    /// Specifies the size of the outputted image: its height in characters (it is twice as wide).
+    /// By default, the map fills the terminal.
+    #[clap(short, long)]
+    size: Option<u16>,
+
+    /// Width of the map, in characters. Overrides the width given by --size or the terminal.
+    #[clap(long)]
+    width: Option<u16>,
+
+    /// Height of the map, in characters. Overrides the height given by --size or the terminal.
+    #[clap(long)]
+    height: Option<u16>,
+
+    /// Characters used to draw the ways: box (box-drawing characters, connected at junctions), ascii
+    /// (one character per way segment, chosen from its direction) or braille (Unicode Braille patterns,
+    /// with a resolution 8 times higher).
+    #[clap(long, arg_enum, default_value = "box")]
+    charset: style::Charset,
+
+    /// Projection used to draw the map: web-mercator (as on openstreetmap.org), equirectangular or utm.
+    #[clap(long, arg_enum, default_value = "web-mercator")]
+    projection: geo::Projection,
+
+    /// When to color the map: auto (only in a terminal, unless the NO_COLOR environment variable is set), always or never.
+    /// 24-bit colors are used when the COLORTERM environment variable announces them, and 256 colors when TERM does (ex: xterm-256color).
+    #[clap(long, arg_enum, default_value = "auto")]
+    color: cell::ColorChoice,
+
+    /// Theme giving the characters and colors of the map: default, dark, light, monochrome or high-contrast (colorblind-safe),
+    /// the name of a theme file of the configuration directory (~/.config/rustreet/themes/<THEME>.toml on Linux),
+    /// or the path of a theme file. Defaults to the theme of the configuration file (~/.config/rustreet/config.toml on Linux).
+    #[clap(long)]
+    theme: Option<String>,
 
-    /// Specifies the level of details, between 0 and 6. 0 is the most detailled and 6 the less.
+    /// Specifies the level of details, between 0 and 6. 0 only shows the major roads and rivers, and 6 shows every way.
     /// The default value depends on the size of the displayed area.
     #[clap(short, long)]
-    details_lvl: Option<u16>,
+    details_lvl: Option<u8>,
+
+    /// Layers to request and display, separated by commas (roads, water, rail, areas, boundaries, routes, buildings, poi, places, street-names). Every layer is displayed by default.
+    /// Buildings, points of interest and street names are only displayed at the most detailed levels.
+    #[clap(long, arg_enum, use_delimiter = true)]
+    layers: Vec<style::Layer>,
+
+    /// Classes of points of interest to display, separated by commas (fuel, pharmacy, hospital, restaurant, cafe, station,
+    /// school, bank, parking, post-office, police, worship). Every class is displayed by default.
+    #[clap(long, arg_enum, use_delimiter = true)]
+    poi: Vec<style::PoiClass>,
 
     /// Set the timeout for requests to Overpass API.
     #[clap(short, long, default_value_t = 30)]
@@ -102,6 +335,39 @@ struct Args {
     /// If specified, will display information messages. Don't work in interactive mode.
     #[clap(long)]
     info: bool,
+
+    /// Don't read nor write the responses of the APIs in the disk cache.
+    #[clap(long)]
+    no_cache: bool,
+
+    /// Ignore the responses stored in the disk cache and replace them with new ones.
+    #[clap(long)]
+    refresh: bool,
+
+    /// Time (in hours) after which a response stored in the disk cache is outdated.
+    #[clap(long, default_value_t = cache::DEFAULT_TTL_HOURS)]
+    cache_ttl: u64,
+
+    /// Maximum size (in MB) of the disk cache. The oldest responses are removed when it is exceeded.
+    #[clap(long, default_value_t = cache::DEFAULT_MAX_SIZE_MB)]
+    cache_size: u64,
+
+    /// URL of a Nominatim endpoint. Can be given several times: the next endpoint is used when one fails.
+    #[clap(long, multiple_occurrences = true)]
+    nominatim_url: Vec<String>,
+
+    /// URL of an Overpass endpoint. Can be given several times: the next endpoint is used when one fails.
+    /// Defaults to a list of public mirrors.
+    #[clap(long, multiple_occurrences = true)]
+    overpass_url: Vec<String>,
+
+    /// Number of retries of a failed request on each endpoint.
+    #[clap(long, default_value_t = api_wrapper::DEFAULT_MAX_RETRIES)]
+    retries: u32,
+
+    /// Delay (in milliseconds) before the first retry of a failed request. It is doubled at each new retry.
+    #[clap(long, default_value_t = api_wrapper::DEFAULT_RETRY_DELAY_MS)]
+    retry_delay: u64,
 }
 
 
@@ -115,11 +381,9 @@ fn main() {
 
     
     // Run Rustreet in interactive or one-shot mode
-    if args.interactive {
-
-    }
+    let result = if args.interactive {interactive::run(&args)} else {one_shot(&args)};
 
-    else {
-        one_shot(&args);
+    if let Err(err) = result {
+        exit_with_error(err);
     }
 }
\ No newline at end of file
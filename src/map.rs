@@ -1,12 +1,15 @@
 use crate::geo;
-use crate::style::get_road_repr;
+use crate::style;
 use crate::style::get_way_index;
-use crate::ascii_map::AsciiMap;
+use crate::ascii_map::{self, AsciiMap, CELL_RATIO};
+use crate::cell::{self, Cell, FeatureRef};
+use crate::osm;
+use crate::osm::{MemberType, OsmData};
+use crate::raster;
+use crate::theme::Theme;
 
 use std::f64::consts::PI;
-use std::collections::HashMap;
-use std::cmp::Ordering;
-use json;
+use std::collections::{HashMap, HashSet};
 
 
 
@@ -20,324 +23,1147 @@ struct Node {
     id: u64,
     lat: f64,
     lon: f64,
+    poi: Option<style::PoiClass>,  // Class of the point of interest this node is, if any
+}
+
+
 
-    previous_lat: Option<f64>,
-    previous_lon: Option<f64>,
-    next_lat: Option<f64>,
-    next_lon: Option<f64>,
 
-    way_type: Option<usize>,
+
+/// A named place (a city, a town...), labelled on the map
+struct Place {
+    node: Node,
+    place_type: String,     // Value of the "place" tag of the node (see style::get_place_types)
+    name: String,
+    population: u64,        // Population of the place, 0 if unknown
+}
+
+
+
+
+
+#[derive(Clone)]
+struct Way {
+    nodes: Vec<Node>,               // List of nodes of the way
+    tags: HashMap<String, String>,  // Tags of this way (like "highway", "lanes", "max_speed", etc.)
+    other_rings: Vec<Vec<Node>>,    // For a multipolygon, its other rings: the other outer ones and the inner ones (holes)
+    feature_ref: FeatureRef,        // OpenStreetMap element drawn: the way itself, or the relation it is a part of
 }
 
 
-impl Node {
-    /// Return the angle (in degrees) of this Node relative to the East-West direction.
-    /// 
-    /// The angle is determined relative to the previous node coordinates (if any) and
-    /// the next node coordinates (if any).
-    /// If the node is alone (no previous nor next nodes), the function will return 0.
-    fn get_angle(&self) -> f64 {
+impl Way {
+
+    /// Return the way type of that way
+    fn way_type(&self) -> &str {
+        osm::way_type(&self.tags)
+    }
+
+
+
+    /// Return the way type index of this way
+    fn way_index(&self) -> Option<usize> {
+        if self.way_type() == "" {Option::None}
+        else {get_way_index(self.way_type())}
+    }
+
+
+
+    /// Return true if this way is in a tunnel
+    fn is_tunnel(&self) -> bool {
+        self.tags.get("tunnel").is_some_and(|tunnel| tunnel != "no")
+    }
+
+
+
+
+    /// Return true if this way is closed: its last node is its first one
+    fn is_closed(&self) -> bool {
+        self.nodes.len() > 3 && self.nodes.first().map(|node| node.id) == self.nodes.last().map(|node| node.id)
+    }
+
+
+
+
+    /// Return the building category of this way (see style::get_building_index), or None if it is not a building
+    fn building_index(&self) -> Option<usize> {
+        self.tags.get("building").map(|building_type| style::get_building_index(building_type))
+    }
+
+
+
+
+    /// Return the boundary category of this way (see style::get_boundary_index) with its admin level,
+    /// or None if it is not a part of an administrative boundary
+    fn boundary(&self) -> Option<(usize, u8)> {
+        let admin_level: u8 = self.tags.get("admin_level")?.parse().ok()?;
+        Some((style::get_boundary_index(admin_level), admin_level))
+    }
 
-        // If nor previous_lat or next_lat are specified, return 0
-        if self.previous_lat.is_none() && self.next_lat.is_none() {return 0.0;}
 
 
-        let d_lat: f64;
-        let d_lon: f64;
 
-        // Choose the 2 points used to compute the angle
-        if self.previous_lat.is_some() && self.next_lat.is_some() {
-            d_lat = self.next_lat.unwrap() - self.previous_lat.unwrap();
-            d_lon = self.next_lon.unwrap() - self.previous_lon.unwrap();
+    /// Return the cell representing a segment of this way with the given angle, in the given theme
+    fn get_cell(&self, theme: &Theme, angle: f64) -> Cell {
+        match self.way_index() {
+            Some(way_index) => way_cell(self, theme.get_road_repr(way_index, angle)),
+            None => Cell::blank(),
         }
-        else if self.previous_lat.is_some() {
-            d_lat = self.lat - self.previous_lat.unwrap();
-            d_lon = self.lon - self.previous_lon.unwrap();
+    }
+}
+
+
+
+
+
+/// Structure representing the ascii map with metadatas
+pub struct MetaMap {
+    display_box: geo::BoundingBox,         // Only the nodes contained in this box will be displayed
+    ways: Vec<Way>,                        // List of ways
+    buildings: Vec<Way>,                   // List of buildings (closed ways around their footprint)
+    areas: Vec<Way>,                       // List of filled areas (closed ways or multipolygons of lakes, forests, parks...), largest first
+    routes: Vec<Way>,                      // List of the parts of the routes (bus lines), each one made of consecutive ways
+    boundaries: Vec<Way>,                  // List of the parts of the administrative boundaries
+    places: Vec<Place>,                    // List of the named places, in the order they are labelled (by rank, then by population)
+    points_of_interest: Vec<Node>,         // List of the nodes that are points of interest, part of a way (as stations) or not
+    topology: HashMap<u64, Vec<usize>>,    // Indexes of the ways each node belongs to, by node id
+    detail_lvl: u8,                        // Only the ways of this level of details will be displayed
+    layers: Vec<style::Layer>,             // Only the ways of these layers will be displayed
+    poi_classes: Vec<style::PoiClass>,     // Only the points of interest of these classes will be displayed
+    charset: style::Charset,               // Characters used to draw the ways
+    theme: Theme,                          // Characters and colors of the elements of the map
+    projection: geo::Projection,           // Projection used to place the nodes on the map
+    projected_box: [f64; 4],               // Projected display box: [min northing, min easting, max northing, max easting]
+
+    pub display_height: u16,               // height of the ASCII Map, in characters. don't take the borders into account
+    pub display_width: u16,                // width of the ASCII Map, in characters. don't take the borders into account
+}
+
+
+impl MetaMap {
+
+
+    /// Build the map from the given OSM data, drawn with the given theme. The aspect ratio of the display box
+    /// should be the one of the map on the terminal (see display_ratio).
+    pub fn from(data: OsmData, display_box: geo::BoundingBox, height: u16, width: u16, theme: Theme) -> MetaMap {
+        let mut map = MetaMap {
+            display_box,
+            ways: Vec::new(),
+            buildings: Vec::new(),
+            areas: Vec::new(),
+            routes: Vec::new(),
+            boundaries: Vec::new(),
+            places: Vec::new(),
+            points_of_interest: Vec::new(),
+            topology: HashMap::new(),
+            detail_lvl: style::MAX_DETAIL_LVL,
+            layers: style::ALL_LAYERS.to_vec(),
+            poi_classes: style::ALL_POI_CLASSES.to_vec(),
+            charset: style::Charset::Box,
+            theme,
+            projection: geo::Projection::WebMercator,
+            projected_box: [0.0; 4],
+            display_height: height,
+            display_width: width,
+        };
+        map.update_projected_box();
+
+
+        // List every nodes in this temporary hashmap.
+        let mut nodes: HashMap<u64, Node> = HashMap::new();
+
+        for osm_node in &data.nodes {
+            let node = Node {
+                id: osm_node.id,
+                lat: osm_node.lat,
+                lon: osm_node.lon,
+                poi: style::PoiClass::from_tags(&osm_node.tags),
+            };
+
+            nodes.insert(node.id, node);
+
+            if let (Some(place_type), Some(name)) = (osm_node.tags.get("place"), osm_node.tags.get("name")) {
+                if style::get_place_rank(place_type).is_none() {continue;}
+
+                // The population is sometimes written with separators ("12 345")
+                let population = osm_node.tags.get("population")
+                    .and_then(|population| population.chars().filter(char::is_ascii_digit).collect::<String>().parse().ok())
+                    .unwrap_or(0);
+                map.places.push(Place {node, place_type: place_type.clone(), name: name.clone(), population});
+            }
         }
-        else {
-            d_lat = self.next_lat.unwrap() - self.lat;
-            d_lon = self.next_lon.unwrap() - self.lon;
+
+
+        // Return the nodes of the given ids found in the hashmap
+        let to_nodes = |node_ids: &[u64]| -> Vec<Node> {node_ids.iter().filter_map(|node_id| nodes.get(node_id)).copied().collect()};
+
+
+        // Assemble the relations from their ways, before the ways are moved to the map
+        let mut seen_relations: HashSet<u64> = HashSet::new();
+        for relation in &data.relations {
+
+            // The same relation can be in the responses of several requests
+            if !seen_relations.insert(relation.id) {continue;}
+
+            let feature_ref = (MemberType::Relation, relation.id);
+            if relation.is_boundary() {
+                for part in data.join_ways(&relation.way_members(&["outer", "inner", ""])) {
+                    map.boundaries.push(Way {nodes: to_nodes(&part), tags: relation.tags.clone(), other_rings: Vec::new(), feature_ref});
+                }
+            }
+
+            else if relation.is_multipolygon() {
+
+                // Outer and inner rings are joined separately. Only closed rings can be filled.
+                let closed_rings = |roles: &[&str]| -> Vec<Vec<Node>> {
+                    data.join_ways(&relation.way_members(roles)).into_iter()
+                        .filter(|ring| ring.len() > 3 && ring.first() == ring.last())
+                        .map(|ring| to_nodes(&ring))
+                        .collect()
+                };
+
+                let mut outer_rings = closed_rings(&["outer", ""]);
+                if outer_rings.is_empty() || style::get_area_index(&relation.tags).is_none() {continue;}
+
+                let mut other_rings = outer_rings.split_off(1);
+                other_rings.append(&mut closed_rings(&["inner"]));
+                map.areas.push(Way {nodes: outer_rings.remove(0), tags: relation.tags.clone(), other_rings, feature_ref});
+            }
+
+            else if relation.is_route() {
+                for part in data.join_ways(&relation.way_members(&["", "forward", "backward"])) {
+                    map.routes.push(Way {nodes: to_nodes(&part), tags: relation.tags.clone(), other_rings: Vec::new(), feature_ref});
+                }
+            }
+        }
+
+
+        // Create the Way structs and retrieve each of their nodes from the hashmap.
+        // Nodes are copied, not removed: a node shared by several ways (an intersection) belongs to each of them.
+        for osm_way in data.ways {
+            let mut way = Way {
+                nodes: Vec::new(),
+                tags: osm_way.tags,
+                other_rings: Vec::new(),
+                feature_ref: (MemberType::Way, osm_way.id),
+            };
+
+            // Add this way's nodes
+            for node_id in &osm_way.nodes {
+                if let Some(node) = nodes.get(node_id) {
+                    way.nodes.push(*node);
+                }
+            }
+
+            if way.building_index().is_some() {map.buildings.push(way);}
+            else if way.is_closed() && style::get_area_index(&way.tags).is_some() {map.areas.push(way);}
+            else {map.ways.push(way);}
+        }
+
+        // Store the points of interest apart, to draw them on top of the ways
+        map.points_of_interest = nodes.values().filter(|node| node.poi.is_some()).copied().collect();
+        map.points_of_interest.sort_by_key(|node| node.id);
+
+        // The most important places are labelled first, so they are never hidden by smaller ones
+        map.places.sort_by_key(|place| (style::get_place_rank(&place.place_type), std::cmp::Reverse(place.population), place.node.id));
+        map.places.dedup_by_key(|place| place.node.id);
+
+        // Smaller areas are drawn over the larger ones they are in (a pond in a park), as they all have the same priority
+        map.areas.sort_by(|a, b| extent_size(b).total_cmp(&extent_size(a)));
+
+        // Link each node to the ways it belongs to, once the ways have their final index
+        for (way_index, way) in map.ways.iter().enumerate() {
+            for node in &way.nodes {
+                let node_ways = map.topology.entry(node.id).or_default();
+
+                // Closed ways contain their first node twice
+                if node_ways.last() != Some(&way_index) {node_ways.push(way_index);}
+            }
         }
 
-        // Compute the angle and return it in degrees
-        ((d_lat / d_lon).atan() * 180.0 / PI) + 90.0
+        // Returned finished map struct
+        map
     }
 
 
-    fn get_string_rep(&self) -> String {
-        if self.way_type.is_none() {String::from(" ")}
-        else {get_road_repr(self.way_type.unwrap(), self.get_angle())}
+
+
+
+    /// Return the indexes (in the ways of the map) of the ways the given node belongs to
+    pub fn ways_at(&self, node_id: u64) -> &[usize] {
+        match self.topology.get(&node_id) {
+            Some(node_ways) => node_ways,
+            None => &[],
+        }
     }
-}
 
 
 
 
 
-#[derive(Clone)]
-struct Way {
-    nodes: Vec<Node>,               // List of nodes of the way
-    tags: HashMap<String, String>,  // Tags of this way (like "highway", "lanes", "max_speed", etc.)
-}
+    /// Change the area of the map that will be displayed by generate_ascii_map.
+    /// Only the already loaded ways are displayed: the new box should be contained in the area
+    /// the data were requested for.
+    pub fn set_display_box(&mut self, display_box: geo::BoundingBox) {
+        self.display_box = display_box;
+        self.update_projected_box();
+    }
+
+
 
 
-impl Way {
 
-    fn default_str() -> String {
-        String::from(" ")
+    /// Change the level of details of the displayed ways. Ways of a greater level of details are loaded
+    /// but not displayed (by default, every loaded way is displayed).
+    pub fn set_detail_lvl(&mut self, detail_lvl: u8) {
+        self.detail_lvl = detail_lvl;
     }
 
 
 
-    /// Return the way type of that way
-    fn way_type(&self) -> &str {
-        if self.tags.contains_key("highway") {self.tags["highway"].as_str()}
-        else if self.tags.contains_key("waterway") {self.tags["waterway"].as_str()}
-        else {""}
+
+
+    /// Change the layers of the displayed ways (by default, every layer is displayed)
+    pub fn set_layers(&mut self, layers: &[style::Layer]) {
+        self.layers = layers.to_vec();
     }
 
 
 
-    /// Return the way type index of this way
-    fn way_index(&self) -> Option<usize> {
-        if self.way_type() == "" {Option::None}
-        else {get_way_index(self.way_type())}
+
+
+    /// Change the classes of the displayed points of interest (by default, every class is displayed)
+    pub fn set_poi_classes(&mut self, poi_classes: &[style::PoiClass]) {
+        self.poi_classes = poi_classes.to_vec();
     }
 
 
 
-    /// Compare the importance value of both ways. Used to sort the way vector
-    fn compare(&self, other: &Way) -> Result<Ordering, &'static str> {
-        if self.way_index() == other.way_index() {Ok(Ordering::Equal)}
-        else if self.way_index() < other.way_index() {Ok(Ordering::Less)}
-        else if self.way_index() > other.way_index() {Ok(Ordering::Greater)}
 
-        else {Err("Can't compare way indexes.")}
+
+    /// Return the filter of the displayed elements
+    fn element_filter(&self) -> style::ElementFilter {
+        style::ElementFilter::new(self.detail_lvl, &self.layers, &self.poi_classes)
+    }
+
+
+
+
+
+    /// Change the set of characters used to draw the ways (by default, box-drawing characters)
+    pub fn set_charset(&mut self, charset: style::Charset) {
+        self.charset = charset;
+    }
+
+
+
+
+
+    /// Change the projection used to place the nodes on the map (by default, Web Mercator)
+    pub fn set_projection(&mut self, projection: geo::Projection) {
+        self.projection = projection;
+        self.update_projected_box();
+    }
+
+
+
+
+
+    /// Compute the projected display box: the smallest rectangle of the projected plane containing the corners of the display box
+    fn update_projected_box(&mut self) {
+        let coo = self.display_box.coo;
+        let center = self.display_box.center();
+        let corners = [[coo[0], coo[1]], [coo[0], coo[3]], [coo[2], coo[1]], [coo[2], coo[3]]];
+
+        let projected: Vec<[f64; 2]> = corners.iter().map(|corner| self.projection.project(*corner, center)).collect();
+        self.projected_box = [
+            projected.iter().map(|point| point[0]).fold(f64::INFINITY, f64::min),
+            projected.iter().map(|point| point[1]).fold(f64::INFINITY, f64::min),
+            projected.iter().map(|point| point[0]).fold(f64::NEG_INFINITY, f64::max),
+            projected.iter().map(|point| point[1]).fold(f64::NEG_INFINITY, f64::max),
+        ];
+    }
+
+
+
+
+
+    /// Return the (row, column) coordinates of the given node in the ascii map, as floats.
+    /// The coordinates are outside of [0, display_height] x [0, display_width] if the node is not in the display box.
+    fn get_map_coordinates(&self, node: Node) -> [f64; 2] {
+        let point = self.projection.project([node.lat, node.lon], self.display_box.center());
+        let rel_northing = point[0] - self.projected_box[0];    // northing - min_northing
+        let rel_easting = point[1] - self.projected_box[1];     // easting - min_easting
+
+        [
+            rel_northing / (self.projected_box[2] - self.projected_box[0]) * self.display_height as f64,
+            rel_easting / (self.projected_box[3] - self.projected_box[1]) * self.display_width as f64,
+        ]
     }
 
 
 
+    /// Return the cells of a grid of the given size crossed by the segment between the 2 given nodes,
+    /// with the angle of the segment (in the convention of style::get_orientation), or None if the segment
+    /// is outside of the display box. The grid covers the display box.
+    fn rasterize_segment(&self, start: Node, end: Node, rows: usize, columns: usize) -> Option<(f64, Vec<[usize; 2]>)> {
+        let scale = [rows as f64 / self.display_height as f64, columns as f64 / self.display_width as f64];
+        let to_grid = |point: [f64; 2]| [point[0] * scale[0], point[1] * scale[1]];
+
+        let start = to_grid(self.get_map_coordinates(start));
+        let end = to_grid(self.get_map_coordinates(end));
+        let (start, end) = raster::clip_segment(start, end, rows as f64, columns as f64)?;
+
+        // Angle of the segment as displayed on the terminal, where characters are higher than wide
+        // (90 for an horizontal segment, 0 or 180 for a vertical one)
+        let d_row = (end[0] - start[0]) / scale[0] * CELL_RATIO;
+        let d_column = (end[1] - start[1]) / scale[1];
+        let angle = (d_row / d_column).atan() * 180.0 / PI + 90.0;
+
+        // Points on the top or right border of the map belong to the last row or column
+        let to_cell = |point: [f64; 2]| [
+            (point[0].floor() as i64).clamp(0, rows as i64 - 1),
+            (point[1].floor() as i64).clamp(0, columns as i64 - 1),
+        ];
+
+        let cells = raster::line_cells(to_cell(start), to_cell(end)).iter().map(|cell| [cell[0] as usize, cell[1] as usize]).collect();
+        Some((angle, cells))
+    }
 
-    /// Append a node to this way. Modify the node to change previous_lat/lon and next_lat/lon values
-    fn add_node(&mut self, mut node: Node) {
-        if self.nodes.len() > 0 {
-            let last_node_id = self.nodes.len() - 1;
 
-            self.nodes[last_node_id].next_lat = Some(node.lat);
-            self.nodes[last_node_id].next_lon = Some(node.lon);
 
-            node.previous_lat = Some(self.nodes[last_node_id].lat);
-            node.previous_lon = Some(self.nodes[last_node_id].lon);
+    /// Return the (row, column) coordinates in the ascii map of the rings of the given closed way or multipolygon
+    fn polygon_rings(&self, way: &Way) -> Vec<Vec<[f64; 2]>> {
+        std::iter::once(&way.nodes).chain(way.other_rings.iter())
+            .map(|ring| ring.iter().map(|node| self.get_map_coordinates(*node)).collect())
+            .collect()
+    }
+
+
+
+    /// Return the cells of the map covered by the given closed way. A way smaller than a cell covers the cell of its centroid.
+    fn polygon_cells(&self, way: &Way) -> Vec<[usize; 2]> {
+        let (height, width) = (self.display_height as usize, self.display_width as usize);
+
+        let rings = self.polygon_rings(way);
+        let points = &rings[0];
+        if points.is_empty() {return Vec::new();}
+
+        let mut covered = raster::polygon_cells(&rings, height, width);
+        if covered.is_empty() {
+            let centroid = [
+                points.iter().map(|point| point[0]).sum::<f64>() / points.len() as f64,
+                points.iter().map(|point| point[1]).sum::<f64>() / points.len() as f64,
+            ];
+            if centroid[0] >= 0.0 && centroid[1] >= 0.0 && centroid[0] < height as f64 && centroid[1] < width as f64 {
+                covered.push([centroid[0] as usize, centroid[1] as usize]);
+            }
         }
 
-        self.nodes.push(node);
+        covered
     }
 
 
 
+    /// Return the cells of the map crossed by each displayed administrative boundary, with the category of the boundary
+    /// and the boundary itself
+    fn boundary_cells(&self) -> Vec<(usize, &Way, Vec<[usize; 2]>)> {
+        let (height, width) = (self.display_height as usize, self.display_width as usize);
+        let admin_levels = self.element_filter().admin_levels;
 
-    /// Create n new Node objects to between each Nodes of this way to add more information about the path.
-    /// This will prevent the way to be displayed fragmented.
-    /// NOTE: The nodes MUST BE CORRECTLY SORTED for this to work
-    fn interpolate_nodes(&mut self, n: u32) {
+        let mut result: Vec<(usize, &Way, Vec<[usize; 2]>)> = Vec::new();
 
-        // Iterate over each node until the last one
-        let mut i: usize = 0;
+        for boundary in &self.boundaries {
+            let boundary_index = match boundary.boundary() {
+                Some((boundary_index, admin_level)) if admin_levels.contains(&admin_level) => boundary_index,
+                _ => continue,
+            };
 
-        // Prevent attempts to subtract with overflow
-        if self.nodes.len() == 0 {return;}
+            let cells: Vec<[usize; 2]> = boundary.nodes.windows(2)
+                .filter_map(|segment| self.rasterize_segment(segment[0], segment[1], height, width))
+                .flat_map(|(_, cells)| cells)
+                .collect();
+            if !cells.is_empty() {result.push((boundary_index, boundary, cells));}
+        }
 
-        while i < (self.nodes.len() - 1) {
+        result
+    }
 
-            let current_node = self.nodes[i];
-            let next_node = self.nodes[i+1];
 
-            let delta_lon = next_node.lon - current_node.lon;
-            let delta_lat = next_node.lat - current_node.lat;
 
-            let step_lon = delta_lon / (n + 1) as f64;
-            let step_lat = delta_lat / (n + 1) as f64;
+    /// Return the cells of the map without any way: the filled areas, the buildings on top of them
+    /// when buildings are displayed, and the administrative boundaries
+    fn background_cells(&self) -> Vec<Vec<Cell>> {
+        let (height, width) = (self.display_height as usize, self.display_width as usize);
+        let mut cells: Vec<Vec<Cell>> = vec![vec![Cell::blank(); width]; height];
+        let element_filter = self.element_filter();
 
-            // Add n nodes between current_node and next_node. Modify i accordingly
-            for _ in 0..n {
+        for area in &self.areas {
+            if !element_filter.keeps_area(&area.tags) {continue;}
+            let area_index = match style::get_area_index(&area.tags) {
+                Some(area_index) => area_index,
+                None => continue,
+            };
 
-                // Create a new node object
-                let new_node = Node {
-                    id: 0,
-                    lat: self.nodes[i].lat + step_lat,
-                    lon: self.nodes[i].lon + step_lon,
-                    
-                    previous_lat: Some(self.nodes[i].lat),
-                    previous_lon: Some(self.nodes[i].lon),
-                    next_lat: Option::None,
-                    next_lon: Option::None,
+            // Areas smaller than a cell are not worth drawing
+            let repr = self.theme.get_area_repr(area_index).with_feature(area.feature_ref);
+            for cell in raster::polygon_cells(&self.polygon_rings(area), height, width) {
+                cells[cell[0]][cell[1]].draw(repr);
+            }
+        }
 
-                    way_type: self.nodes[i].way_type,
-                };
+        for building in &self.buildings {
+            if !element_filter.buildings {break;}
+            let repr = match building.building_index() {
+                Some(building_index) => self.theme.get_building_repr(building_index).with_feature(building.feature_ref),
+                None => continue,
+            };
 
-                i += 1;
-                self.nodes.insert(i, new_node);
+            for cell in self.polygon_cells(building) {
+                cells[cell[0]][cell[1]].draw(repr);
+            }
+        }
 
-                // Add to the previous node the coordinates of its next node
-                self.nodes[i - 1].next_lat = Some(self.nodes[i].lat);
-                self.nodes[i - 1].next_lon = Some(self.nodes[i].lon);
+        // A border shared by a country and its regions is drawn as a country border, which has a higher priority
+        for (boundary_index, boundary, boundary_cells) in self.boundary_cells() {
+            let repr = self.theme.get_boundary_repr(boundary_index).with_feature(boundary.feature_ref);
+            for cell in boundary_cells {
+                cells[cell[0]][cell[1]].draw(repr);
             }
+        }
 
-            i += 1;
+        cells
+    }
+
+
+
+    /// Draw the segment between the 2 given nodes of a way in the ascii map data.
+    /// The segment is clipped to the display box, so segments crossing the map without any node inside are drawn too.
+    fn draw_segment(&self, data: &mut [Vec<Cell>], way: &Way, start: Node, end: Node) {
+        let (height, width) = (self.display_height as usize, self.display_width as usize);
+
+        if let Some((angle, cells)) = self.rasterize_segment(start, end, height, width) {
+            let repr = way.get_cell(&self.theme, angle);
+            for cell in cells {
+                data[cell[0]][cell[1]].draw(repr);
+            }
         }
     }
-}
 
 
 
 
 
-/// Structure representing the ascii map with metadatas
-pub struct MetaMap {
-    display_box: geo::BoundingBox,         // Only the nodes contained in this box will be displayed
-    ways: Vec<Way>,                        // List of ways
-    lone_nodes: Vec<Node>,                 // List of nodes (not part of any way)
+    /// Generate the map with one character per cell, chosen from the type and the direction of the way
+    fn generate_ascii_data(&self) -> Vec<Vec<Cell>> {
 
-    pub display_height: u16,               // height of the ASCII Map, in characters. Width = display. don't take the borders into account
-}
+        // Initialise map, with the filled areas and the buildings underneath the ways
+        let mut data = self.background_cells();
 
 
-impl MetaMap {
+        // For each segment of each way, we draw the line between its 2 nodes in the asciimap.
+        // The most important ways are drawn over the other ones, as they have a higher priority.
+        let way_types = style::get_way_types(self.detail_lvl, &self.layers);
+        for way in &self.ways {
 
+            // Skip the ways too detailed for the current level of details
+            if !way_types.contains(&way.way_type()) {continue;}
 
-    /// Take the data str (as returned by OverpassData struct) and parse it
-    pub fn from(data: String, display_box: geo::BoundingBox, height: u16) -> MetaMap {
-        let mut map = MetaMap {display_box, ways: Vec::new(), lone_nodes: Vec::new(), display_height: height};
+            for segment in way.nodes.windows(2) {
+                self.draw_segment(&mut data, way, segment[0], segment[1]);
+            }
+        }
+
+        data
+    }
 
-        let json_data: json::JsonValue = json::parse(&data).unwrap();
 
 
-        // List every nodes in this temporary hashmap.
-        // The JSON must have every Nodes in a Way declared BEFORE that way, or nodes won't be properly linked
-        let mut nodes: HashMap<u64, Node> = HashMap::new();
 
-        for element in json_data["elements"].members() {
 
-            // Add the node to the nodes temporary hashmap
-            if element["type"] == "node" {
+    /// Generate the map with box-drawing characters: each cell is connected to the neighbors its ways enter or leave by,
+    /// and drawn with the line weight and the color of the most important way crossing it.
+    /// Only the ways sharing a node connect: a way crossing the most important one without a shared node (on a bridge,
+    /// in a tunnel, or a river under a road) is hidden under it.
+    fn generate_box_data(&self) -> Vec<Vec<Cell>> {
+        let (height, width) = (self.display_height as usize, self.display_width as usize);
 
-                let node = Node {
-                    id: element["id"].as_u64().unwrap(),
-                    lat: element["lat"].as_f64().unwrap(),
-                    lon: element["lon"].as_f64().unwrap(),
+        // Connections of each way crossing each cell (see style::NORTH, etc.), with the index of the way in the map,
+        // and the most important way of the cell with its way type and its index
+        let mut way_connections: Vec<Vec<Vec<(usize, u8)>>> = vec![vec![Vec::new(); width]; height];
+        let mut cell_ways: Vec<Vec<Option<(usize, usize)>>> = vec![vec![Option::None; width]; height];
 
-                    previous_lat: Option::None,
-                    previous_lon: Option::None,
-                    next_lat: Option::None,
-                    next_lon: Option::None,
+        let way_types = style::get_way_types(self.detail_lvl, &self.layers);
+        for (map_index, way) in self.ways.iter().enumerate() {
 
-                    way_type: Option::None, // Will be changed in
+            // Skip the ways too detailed for the current level of details, and the ways without a type
+            if !way_types.contains(&way.way_type()) {continue;}
+            let way_index = match way.way_index() {
+                Some(way_index) => way_index,
+                None => continue,
+            };
+
+            for segment in way.nodes.windows(2) {
+                let cells = match self.rasterize_segment(segment[0], segment[1], height, width) {
+                    Some((_, cells)) => raster::orthogonal_path(&cells),
+                    None => continue,
                 };
-                
-                nodes.insert(node.id, node);
-            }
 
-            // Create the Way struct and retrieve each of its nodes from the hashmap
-            else if element["type"] == "way" {
+                // Connect each cell of the segment to the next one. Rows of the map go from south to north.
+                for step in cells.windows(2) {
+                    let (from, to) = (step[0], step[1]);
+                    let (exit, entry) = if to[0] > from[0] {(style::NORTH, style::SOUTH)}
+                                        else if to[0] < from[0] {(style::SOUTH, style::NORTH)}
+                                        else if to[1] > from[1] {(style::EAST, style::WEST)}
+                                        else {(style::WEST, style::EAST)};
+
+                    for (cell, connection) in [(from, exit), (to, entry)] {
+                        let cell_connections = &mut way_connections[cell[0]][cell[1]];
+                        match cell_connections.iter_mut().find(|(index, _)| *index == map_index) {
+                            Some((_, connections)) => *connections |= connection,
+                            None => cell_connections.push((map_index, connection)),
+                        }
+                    }
+                }
 
-                // List the tags to be added to the way struct
-                let mut tags: HashMap<String, String> = HashMap::new();
-                for entry in element["tags"].entries() {
-                    tags.insert(entry.0.to_string(), entry.1.to_string());
+                // A lower way index is a more important way
+                for cell in cells {
+                    if cell_ways[cell[0]][cell[1]].is_none_or(|(index, _)| way_index < index) {
+                        cell_ways[cell[0]][cell[1]] = Some((way_index, map_index));
+                    }
                 }
+            }
+        }
 
-                let mut way = Way {
-                    nodes: Vec::new(),
-                    tags: tags.clone(),
-                };
+        // Indexes of the ways sharing a node with each way (including itself). Near a junction, the ways sharing
+        // its node also connect in the cells around it, where the lines of both ways go through the same cells.
+        let connected_ways: Vec<HashSet<usize>> = self.ways.iter()
+            .map(|way| way.nodes.iter().flat_map(|node| self.ways_at(node.id)).copied().collect())
+            .collect();
+
+        let mut data = self.background_cells();
+        for row in 0..height {
+            for column in 0..width {
+                if let Some((way_index, map_index)) = cell_ways[row][column] {
+                    let connections = way_connections[row][column].iter()
+                        .filter(|(index, _)| *index == map_index || connected_ways[map_index].contains(index))
+                        .fold(0, |all, (_, connections)| all | connections);
+
+                    data[row][column].draw(way_cell(&self.ways[map_index], self.theme.get_box_repr(way_index, connections)));
+                }
+            }
+        }
+
+        data
+    }
 
-                // Add this way's nodes
-                for node_id in element["nodes"].members() {
-                    let id_as_u64 = node_id.as_u64().unwrap();
 
-                    if nodes.contains_key(&id_as_u64) {
 
-                        // Remove the node from the hashmap
-                        let mut node = nodes.remove(&id_as_u64).unwrap();
 
-                        // Add the way's type to the node
-                        node.way_type = way.way_index();
 
-                        // Push the node to the vector of nodes from the way
-                        way.add_node(node);
+    /// Generate the map with Braille patterns: each cell is 2 dots wide and 4 dots high, so a dot is
+    /// about as wide as high on the terminal. A cell is colored as the most important way crossing it.
+    fn generate_braille_data(&self) -> Vec<Vec<Cell>> {
+        let (height, width) = (self.display_height as usize, self.display_width as usize);
+
+        // Raised dots of each cell, and the most important way of the cell with its way type
+        let mut dots: Vec<Vec<u8>> = vec![vec![0; width]; height];
+        let mut cell_ways: Vec<Vec<Option<(usize, &Way)>>> = vec![vec![Option::None; width]; height];
+
+        let way_types = style::get_way_types(self.detail_lvl, &self.layers);
+        for way in &self.ways {
+
+            // Skip the ways too detailed for the current level of details, and the ways without a type
+            if !way_types.contains(&way.way_type()) {continue;}
+            let way_index = match way.way_index() {
+                Some(way_index) => way_index,
+                None => continue,
+            };
+
+            for segment in way.nodes.windows(2) {
+                let cells = match self.rasterize_segment(segment[0], segment[1], 4 * height, 2 * width) {
+                    Some((_, cells)) => cells,
+                    None => continue,
+                };
+
+                for dot in cells {
+                    let (row, column) = (dot[0] / 4, dot[1] / 2);
+
+                    // Rows of the map go from south to north, but the dots of a cell are numbered from top to bottom
+                    dots[row][column] |= braille_dot(3 - dot[0] % 4, dot[1] % 2);
+
+                    // A lower way index is a more important way
+                    if cell_ways[row][column].is_none_or(|(index, _)| way_index < index) {
+                        cell_ways[row][column] = Some((way_index, way));
                     }
                 }
+            }
+        }
+
+        let mut data = self.background_cells();
+        for row in 0..height {
+            for column in 0..width {
+                if let Some((way_index, way)) = cell_ways[row][column] {
+                    data[row][column].draw(way_cell(way, self.theme.get_braille_repr(way_index, dots[row][column])));
+                }
+            }
+        }
+
+        data
+    }
 
-                // Interpolate the nodes of that way
-                way.interpolate_nodes(map.display_height as u32);
 
 
-                map.ways.push(way);
+
+
+    /// Draw the displayed routes on top of the map data, by recoloring the cells they cross
+    fn draw_routes(&self, data: &mut [Vec<Cell>]) {
+        if !self.element_filter().routes {return;}
+        let (height, width) = (self.display_height as usize, self.display_width as usize);
+
+        for route in &self.routes {
+            for segment in route.nodes.windows(2) {
+                if let Some((_, cells)) = self.rasterize_segment(segment[0], segment[1], height, width) {
+                    for cell in cells {
+                        let repr = self.theme.get_route_repr(data[cell[0]][cell[1]]);
+
+                        // A cell keeps the element it represents, unless it was empty and the route is the only element on it
+                        let feature_ref = repr.feature_ref.unwrap_or(route.feature_ref);
+                        data[cell[0]][cell[1]].draw(repr.with_feature(feature_ref));
+                    }
+                }
             }
         }
+    }
 
-        // At that point, elements in the nodes hashmap are "lone nodes" (not linked to ways). Store them in map.lone_nodes
-        for node_id in nodes.keys() {
-            map.lone_nodes.push(nodes[&node_id]);
-        }
 
-        map.ways.sort_by(|a, b| a.compare(b).unwrap());
-        map.ways.reverse();
 
-        // Returned finished map struct
-        map
+
+
+    /// Draw the displayed points of interest on top of the map data, except on the cells taken by a label,
+    /// and return the classes of the drawn ones
+    fn draw_points_of_interest(&self, data: &mut [Vec<Cell>]) -> Vec<style::PoiClass> {
+        let (height, width) = (self.display_height as f64, self.display_width as f64);
+        let poi_classes = self.element_filter().poi_classes;
+        let mut drawn: Vec<style::PoiClass> = Vec::new();
+
+        for node in &self.points_of_interest {
+            let poi_class = match node.poi {
+                Some(poi_class) if poi_classes.contains(&poi_class) => poi_class,
+                _ => continue,
+            };
+
+            let coordinates = self.get_map_coordinates(*node);
+            if coordinates[0] < 0.0 || coordinates[1] < 0.0 || coordinates[0] >= height || coordinates[1] >= width {continue;}
+
+            let (row, column) = (coordinates[0] as usize, coordinates[1] as usize);
+            if is_taken(data[row][column]) {continue;}
+
+            data[row][column].draw(self.theme.get_poi_repr(poi_class).with_feature((MemberType::Node, node.id)));
+            if !drawn.contains(&poi_class) {drawn.push(poi_class);}
+        }
+
+        // List the classes in the order of style::ALL_POI_CLASSES, so the legend doesn't depend on the data
+        style::ALL_POI_CLASSES.iter().copied().filter(|poi_class| drawn.contains(poi_class)).collect()
     }
 
 
 
 
 
-    pub fn generate_ascii_map(&self) -> AsciiMap {
+    /// Label the displayed places on top of the map data, by order of priority: a marker on the place, and its name
+    /// on its right (or on its left if there is no room). A label is skipped rather than overlapping another one
+    /// or leaving the map, and a place without room for its name only gets a marker.
+    fn draw_places(&self, data: &mut [Vec<Cell>]) {
+        let (height, width) = (self.display_height as usize, self.display_width as usize);
+        let place_types = self.element_filter().place_types;
+
+        for place in &self.places {
+            if !place_types.contains(&place.place_type.as_str()) {continue;}
+            let rank = style::get_place_rank(&place.place_type).unwrap_or_default();
+
+            let coordinates = self.get_map_coordinates(place.node);
+            if coordinates[0] < 0.0 || coordinates[1] < 0.0 || coordinates[0] >= height as f64 || coordinates[1] >= width as f64 {continue;}
+            let (row, column) = (coordinates[0] as usize, coordinates[1] as usize);
+            if is_taken(data[row][column]) {continue;}
 
-        // Initialise map
-        let mut data: Vec<Vec<String>> = Vec::new();
-        for x in 0..self.display_height {
-            data.push(Vec::new());
+            // Candidate labels, as their first column: "◉ Name" on the right of the place, or "Name ◉" on its left.
+            // The width is measured in columns of the terminal: wide characters take two cells, and the zero-width ones are left out
+            let name = cell::display_chars(&place.name);
+            let label_width = name.iter().map(|(_, w)| w).sum::<usize>() + 2;
+            let candidates = [Some(column), (column + 1).checked_sub(label_width)];
 
-            for _ in 0..self.display_height {
-                data[x as usize].push(Way::default_str());
+            // A label must fit in the map, and keep a blank cell between it and the other labels
+            let fits = |start: usize| {
+                start + label_width <= width
+                    && data[row][start.saturating_sub(1)..(start + label_width + 1).min(width)].iter().all(|cell| !is_taken(*cell))
+            };
+
+            let start = candidates.into_iter().flatten().find(|start| fits(*start));
+
+            let feature_ref = (MemberType::Node, place.node.id);
+            data[row][column].draw(self.theme.get_place_marker_repr(rank).with_feature(feature_ref));
+
+            let start = match start {
+                Some(start) => start,
+                None => continue,
+            };
+
+            // The name is written after the marker on the right, before it on the left, separated by a space
+            let name_start = if start == column {column + 2} else {start};
+            let space = if start == column {column + 1} else {column - 1};
+            data[row][space].draw(self.theme.get_place_label_repr(rank, ' ').with_feature(feature_ref));
+            let mut name_column = name_start;
+            for (c, w) in name {
+                let cell = self.theme.get_place_label_repr(rank, c).with_feature(feature_ref);
+                data[row][name_column].draw(cell);
+                if w == 2 {data[row][name_column + 1].draw(cell.continuation());}
+                name_column += w;
             }
         }
+    }
 
 
-        // For each node of each way, we get its coordinate in the asciimap and put the character representing it
-        for way in &self.ways {
 
-            for node in &way.nodes {
 
-                // Get the relative coordinates of the node compared to the display box
-                let rel_lat = node.lat - self.display_box.coo[0]; // lat - min_lat
-                let rel_lon = node.lon - self.display_box.coo[1]; // lon - min_lon
-                
-                // Skip this node if it's not contained in the display box
-                if rel_lat < 0.0 || rel_lon < 0.0 {continue;}
-                if rel_lat > self.display_box.dim_deg[0] || rel_lon > self.display_box.dim_deg[1] {continue;}
 
-                // Get the character coordinates
-                let char_x = rel_lat / self.display_box.dim_deg[0] * self.display_height as f64;
-                let char_x = char_x.floor() as usize;
+    /// Return the cells of the map crossed by the given way, in order
+    fn way_cells(&self, way: &Way) -> Vec<[usize; 2]> {
+        let (height, width) = (self.display_height as usize, self.display_width as usize);
+        let mut cells: Vec<[usize; 2]> = Vec::new();
+
+        for segment in way.nodes.windows(2) {
+            if let Some((_, segment_cells)) = self.rasterize_segment(segment[0], segment[1], height, width) {
+                cells.extend(segment_cells);
+            }
+        }
+
+        // Consecutive segments share their end cell
+        cells.dedup();
+        cells
+    }
+
 
-                let char_y = rel_lon / self.display_box.dim_deg[1] * self.display_height as f64;
-                let char_y = char_y.floor() as usize;
 
-                // If the point is out of bounds, skip without adding it to the data
-                if char_x >= self.display_height as usize || char_y >= self.display_height as usize {continue;}
+    /// Write the names of the displayed streets over them, the most important streets first, on a horizontal or vertical
+    /// stretch of the street long enough for the name (with a cell of the street left at each end). The streets where
+    /// no name fits get a number instead, if there is one left and room for it in the legend. Return the legend of these numbers.
+    fn draw_street_names(&self, data: &mut [Vec<Cell>]) -> Vec<(Cell, String)> {
+        let element_filter = self.element_filter();
+        if !element_filter.street_names {return Vec::new();}
+
+        let mut labelled: HashSet<&str> = HashSet::new();
+        let mut unlabelled: Vec<(&str, FeatureRef, Vec<[usize; 2]>)> = Vec::new();
+
+        // The most important streets first
+        let mut streets: Vec<&Way> = self.ways.iter()
+            .filter(|way| style::get_way_layer(way.way_type()) == Some(style::Layer::Roads) && element_filter.way_types.contains(&way.way_type()))
+            .collect();
+        streets.sort_by_key(|way| way.way_index());
+
+        for way in streets {
+            let name = match way.tags.get("name") {
+                Some(name) if !labelled.contains(name.as_str()) => name.as_str(),
+                _ => continue,
+            };
+            let cells = self.way_cells(way);
+
+            // Split the street into straight stretches: consecutive cells on the same row, or on the same column.
+            // Their cells are sorted in reading order: from left to right, or from top to bottom (rows go from south to north).
+            let mut stretches: Vec<Vec<[usize; 2]>> = Vec::new();
+            let mut start = 0;
+            for i in 1..=cells.len() {
+                let is_continued = i < cells.len() && {
+                    let (first, previous, cell) = (cells[start], cells[i - 1], cells[i]);
+                    let same_row = first[0] == cell[0] && previous[1].abs_diff(cell[1]) == 1;
+                    let same_column = first[1] == cell[1] && previous[0].abs_diff(cell[0]) == 1;
+                    same_row || same_column
+                };
+                if !is_continued {
+                    let mut stretch = cells[start..i].to_vec();
+                    stretch.sort_by_key(|cell| (cell[1], std::cmp::Reverse(cell[0])));
+                    stretch.dedup();
+                    stretches.push(stretch);
+                    start = i;
+                }
+            }
 
-                // Add the way character to the ascii map
-                data[char_x][char_y] = String::from(node.get_string_rep());
+            // Use the longest stretch with room for the name at its middle. The length is measured in columns of the terminal:
+            // a wide character takes two cells of a row, so names with wide characters are never written along a column.
+            let name_chars = cell::display_chars(name);
+            let length: usize = name_chars.iter().map(|(_, width)| width).sum();
+            let is_wide = name_chars.iter().any(|(_, width)| *width > 1);
+            let name_cells = |stretch: &[[usize; 2]]| {
+                let offset = (stretch.len() - length) / 2;
+                stretch[offset..offset + length].to_vec()
+            };
+            stretches.retain(|stretch| {
+                let is_vertical = stretch.len() > 1 && stretch[0][1] == stretch[1][1];
+                stretch.len() >= length + 2 && !(is_wide && is_vertical) && name_cells(stretch).iter().all(|cell| !is_taken(data[cell[0]][cell[1]]))
+            });
+            let stretch = match stretches.into_iter().max_by_key(|stretch| stretch.len()) {
+                Some(stretch) => stretch,
+                None => {
+                    unlabelled.push((name, way.feature_ref, cells));
+                    continue;
+                }
+            };
+
+            let cells = name_cells(&stretch);
+            let mut i = 0;
+            for (c, width) in name_chars {
+                let repr = self.theme.get_street_name_repr(c).with_feature(way.feature_ref);
+                data[cells[i][0]][cells[i][1]].draw(repr);
+                if width == 2 {data[cells[i + 1][0]][cells[i + 1][1]].draw(repr.continuation());}
+                i += width;
             }
+            labelled.insert(name);
+        }
 
+        // Number the remaining streets at the middle of one of their ways, the most important streets first
+        unlabelled.retain(|(name, _, cells)| !labelled.contains(name) && !cells.is_empty());
+
+        let mut legend: Vec<(Cell, String)> = Vec::new();
+        let mut legend_width = ascii_map::SCALE_WIDTH;
+        for (name, feature_ref, cells) in unlabelled {
+            if legend.len() >= self.theme.street_number_count() {break;}
+            if legend.iter().any(|(_, numbered_name)| numbered_name == name) {continue;}
+            if legend_width + ascii_map::legend_entry_width(name) > self.display_width as usize {continue;}
+
+            // The free cell of the way nearest to its middle
+            let middle = cells.len() / 2;
+            let cell = match cells.iter().enumerate().filter(|(_, cell)| !is_taken(data[cell[0]][cell[1]])).min_by_key(|(i, _)| i.abs_diff(middle)) {
+                Some((_, cell)) => *cell,
+                None => continue,
+            };
+
+            let repr = self.theme.get_street_number_repr(legend.len());
+            data[cell[0]][cell[1]].draw(repr.with_feature(feature_ref));
+            legend.push((repr, name.to_string()));
+            legend_width += ascii_map::legend_entry_width(name);
         }
 
+        legend
+    }
+
+
 
-        // Return the AsciiMap
+
+
+    /// Generate the map with the current charset, with a legend of the numbered streets, the displayed points of interest
+    /// and boundaries
+    pub fn generate_ascii_map(&self) -> AsciiMap {
+        let mut data = match self.charset {
+            style::Charset::Box => self.generate_box_data(),
+            style::Charset::Ascii => self.generate_ascii_data(),
+            style::Charset::Braille => self.generate_braille_data(),
+        };
+        self.draw_routes(&mut data);
+
+        // Labels and symbols never overwrite each other: places first, then points of interest and street names
+        self.draw_places(&mut data);
+        let drawn_classes = self.draw_points_of_interest(&mut data);
+        let mut legend = self.draw_street_names(&mut data);
+
+        // Numbered streets come first in the legend, as their numbers mean nothing without it
         let mut ascii_map = AsciiMap::from(self.display_box, data);
-        ascii_map.double();
+        legend.extend(drawn_classes.iter().map(|poi_class| (self.theme.get_poi_repr(*poi_class), String::from(poi_class.name()))));
+
+        // Boundaries of each category, from the country borders to the local ones
+        let mut boundary_indexes: Vec<usize> = self.boundary_cells().iter().map(|(boundary_index, _, _)| *boundary_index).collect();
+        boundary_indexes.sort();
+        boundary_indexes.dedup();
+        legend.extend(boundary_indexes.into_iter().map(|boundary_index| (self.theme.get_boundary_repr(boundary_index), String::from(style::get_boundary_name(boundary_index)))));
+
+        ascii_map.set_legend(legend);
         ascii_map
     }
+}
+
+
+
+
+
+
+/// Return the aspect ratio (width / height) of a map of the given size in characters, as displayed on the terminal
+pub fn display_ratio(height: u16, width: u16) -> f64 {
+    width as f64 / (height as f64 * CELL_RATIO)
+}
+
+
+
+/// Return the given cell (see Theme::get_box_repr, Theme::get_braille_repr...) as a cell of the given way:
+/// dimmed if the way is in a tunnel, and representing it
+fn way_cell(way: &Way, cell: Cell) -> Cell {
+    let cell = if way.is_tunnel() {style::get_tunnel_repr(cell)} else {cell};
+    cell.with_feature(way.feature_ref)
+}
+
+
+
+/// Return true if the given cell is taken by a label or a symbol, which can't be drawn over by another one
+fn is_taken(cell: Cell) -> bool {
+    cell.priority >= style::LABEL_PRIORITY
+}
+
+
+
+/// Return the bit of a Braille pattern for the dot at the given row (from the top, 0 to 3) and column (0 or 1) of a cell
+fn braille_dot(row: usize, column: usize) -> u8 {
+    match (row, column) {
+        (3, 0) => 0x40,
+        (3, _) => 0x80,
+        (_, 0) => 1 << row,
+        (_, _) => 1 << (row + 3),
+    }
+}
+
+
+
+/// Return the size of the extent of the given way, in square degrees
+fn extent_size(way: &Way) -> f64 {
+    let lats = way.nodes.iter().map(|node| node.lat);
+    let lons = way.nodes.iter().map(|node| node.lon);
+
+    let d_lat = lats.clone().fold(f64::NEG_INFINITY, f64::max) - lats.fold(f64::INFINITY, f64::min);
+    let d_lon = lons.clone().fold(f64::NEG_INFINITY, f64::max) - lons.fold(f64::INFINITY, f64::min);
+    d_lat * d_lon
+}
+
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::osm::{OsmNode, OsmWay};
+
+
+    fn node(id: u64, lat: f64, lon: f64) -> OsmNode {
+        OsmNode {id, lat, lon, tags: HashMap::new()}
+    }
+
+
+    fn road(id: u64, nodes: &[u64]) -> OsmWay {
+        OsmWay {id, nodes: nodes.to_vec(), tags: HashMap::from([(String::from("highway"), String::from("residential"))])}
+    }
+
+
+
+    #[test]
+    fn shared_nodes_belong_to_every_way() {
+        // Two roads crossing at node 3, and a closed way around it
+        let data = OsmData {
+            nodes: vec![node(1, 0.0, 0.0), node(2, 0.0, 2.0), node(3, 0.0, 1.0), node(4, 1.0, 1.0), node(5, -1.0, 1.0)],
+            ways: vec![road(10, &[1, 3, 2]), road(11, &[4, 3, 5]), road(12, &[3, 4, 2, 3])],
+            relations: Vec::new(),
+        };
+        let theme = Theme::load("default", std::path::Path::new("")).unwrap();
+        let map = MetaMap::from(data, geo::BoundingBox::new(-1.0, 0.0, 1.0, 2.0), 10, 20, theme);
+
+        assert_eq!(map.ways.len(), 3);
+        for way in &map.ways {
+            assert!(way.nodes.iter().any(|node| node.id == 3));
+        }
+
+        // Closed ways are only listed once for their first node
+        assert_eq!(map.ways_at(3), &[0, 1, 2]);
+        assert_eq!(map.ways_at(1), &[0]);
+        assert_eq!(map.ways_at(4), &[1, 2]);
+        assert!(map.ways_at(6).is_empty());
+    }
+
+
+
+    #[test]
+    fn only_ways_sharing_a_node_are_connected() {
+        let crossing_glyph = |vertical_nodes: &[u64]| {
+            let data = OsmData {
+                nodes: vec![node(1, 0.1, 0.1), node(2, 0.1, 1.9), node(3, 0.1, 1.05), node(4, -0.9, 1.05), node(5, 0.9, 1.05)],
+                ways: vec![road(10, &[1, 3, 2]), road(11, vertical_nodes)],
+                relations: Vec::new(),
+            };
+            let theme = Theme::load("default", std::path::Path::new("")).unwrap();
+            let map = MetaMap::from(data, geo::BoundingBox::new(-1.0, 0.0, 1.0, 2.0), 10, 20, theme);
+
+            let crossing = map.get_map_coordinates(map.ways[0].nodes[1]);
+            map.generate_box_data()[crossing[0] as usize][crossing[1] as usize].glyph
+        };
+
+        // A junction, and a crossing without a shared node (a bridge), where the first road hides the second one
+        assert_eq!(crossing_glyph(&[4, 3, 5]), '┼');
+        assert_eq!(crossing_glyph(&[4, 5]), '─');
+    }
+
+
+
+    #[test]
+    fn roads_crossing_the_view_are_drawn_from_border_to_border() {
+        // Both ends of the road are outside of the view
+        let data = OsmData {
+            nodes: vec![node(1, 0.1, -1.0), node(2, 0.1, 3.0)],
+            ways: vec![road(10, &[1, 2])],
+            relations: Vec::new(),
+        };
+        let theme = Theme::load("default", std::path::Path::new("")).unwrap();
+        let map = MetaMap::from(data, geo::BoundingBox::new(-1.0, 0.0, 1.0, 2.0), 10, 20, theme);
+
+        let cells = map.way_cells(&map.ways[0]);
+        assert_eq!(cells.len(), 20);
+        assert!(cells.iter().all(|cell| cell[0] == cells[0][0]));
+        assert_eq!(cells.iter().map(|cell| cell[1]).collect::<Vec<usize>>(), (0..20).collect::<Vec<usize>>());
+    }
+
+
+
+    #[test]
+    fn wide_place_names_take_two_cells_per_character() {
+        let mut city = node(1, 0.0, 0.5);
+        city.tags = HashMap::from([(String::from("place"), String::from("city")), (String::from("name"), String::from("東京"))]);
+        let data = OsmData {nodes: vec![city], ways: Vec::new(), relations: Vec::new()};
+        let theme = Theme::load("default", std::path::Path::new("")).unwrap();
+        let map = MetaMap::from(data, geo::BoundingBox::new(-1.0, 0.0, 1.0, 2.0), 10, 20, theme);
+
+        let mut cells = vec![vec![Cell::blank(); 20]; 10];
+        map.draw_places(&mut cells);
+
+        // "◉ 東京": the marker, a space, and the two characters with their continuation cells
+        let row = cells.iter().find(|row| row.iter().any(|cell| cell.glyph == '東')).unwrap();
+        let line = crate::cell::to_line(row, crate::cell::ColorMode::Plain);
+        assert_eq!(line.trim(), "◉ 東京");
+        assert_eq!(line.chars().count(), 20 - 2);
+        assert_eq!(row.iter().filter(|cell| is_taken(**cell)).count(), 6);
+    }
+
+
+
+    #[test]
+    fn wide_street_names_take_two_cells_per_character() {
+        let named_road = |id: u64, nodes: &[u64], name: &str| {
+            let mut way = road(id, nodes);
+            way.tags.insert(String::from("name"), String::from(name));
+            way
+        };
+
+        // A horizontal street, and a vertical one whose name can't be written along its column
+        let data = OsmData {
+            nodes: vec![node(1, -0.5, 0.0), node(2, -0.5, 2.0), node(3, -1.0, 1.5), node(4, 1.0, 1.5)],
+            ways: vec![named_road(10, &[1, 2], "東京通り"), named_road(11, &[3, 4], "横浜")],
+            relations: Vec::new(),
+        };
+        let theme = Theme::load("default", std::path::Path::new("")).unwrap();
+        let map = MetaMap::from(data, geo::BoundingBox::new(-1.0, 0.0, 1.0, 2.0), 10, 40, theme);
+
+        let mut cells = vec![vec![Cell::blank(); 40]; 10];
+        let legend = map.draw_street_names(&mut cells);
+
+        let row = cells.iter().find(|row| row.iter().any(|cell| cell.glyph == '東')).unwrap();
+        let line = crate::cell::to_line(row, crate::cell::ColorMode::Plain);
+        assert_eq!(line.trim(), "東京通り");
+        assert_eq!(line.chars().count(), 40 - 4);
+
+        assert!(cells.iter().flatten().all(|cell| cell.glyph != '横'));
+        assert_eq!(legend.len(), 1);
+        assert_eq!(legend[0].1, "横浜");
+    }
 }
\ No newline at end of file
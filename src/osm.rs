@@ -0,0 +1,350 @@
+use crate::geo;
+use crate::raster;
+use crate::style;
+use crate::error::Error;
+
+use std::collections::{HashMap, HashSet};
+
+
+
+/// A node as stored in OpenStreetMap data
+#[derive(Clone)]
+pub struct OsmNode {
+    pub id: u64,
+    pub lat: f64,
+    pub lon: f64,
+    pub tags: HashMap<String, String>,
+}
+
+
+/// A way as stored in OpenStreetMap data: an ordered list of node ids
+#[derive(Clone)]
+pub struct OsmWay {
+    pub id: u64,
+    pub nodes: Vec<u64>,
+    pub tags: HashMap<String, String>,
+}
+
+
+/// Type of the element a member of a relation refers to
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum MemberType {
+    Node,
+    Way,
+    Relation,
+}
+
+
+impl MemberType {
+
+    /// Return the member type of the given name ("node", "way" or "relation"), as written in the OSM data
+    pub fn from(name: &str) -> Option<MemberType> {
+        match name {
+            "node" => Some(MemberType::Node),
+            "way" => Some(MemberType::Way),
+            "relation" => Some(MemberType::Relation),
+            _ => Option::None,
+        }
+    }
+}
+
+
+/// A member of a relation: an element, with its role in the relation (as "outer" or "inner" for multipolygons)
+#[derive(Clone)]
+pub struct OsmMember {
+    pub member_type: MemberType,
+    pub id: u64,
+    pub role: String,
+}
+
+
+/// A relation as stored in OpenStreetMap data: an ordered list of members
+#[derive(Clone)]
+pub struct OsmRelation {
+    pub id: u64,
+    pub members: Vec<OsmMember>,
+    pub tags: HashMap<String, String>,
+}
+
+
+impl OsmRelation {
+
+    /// Return true if this relation is a multipolygon (an area made of several ways)
+    pub fn is_multipolygon(&self) -> bool {
+        self.tags.get("type").is_some_and(|relation_type| relation_type == "multipolygon")
+    }
+
+
+    /// Return true if this relation is an administrative boundary (the border of a country, a region, a city...)
+    pub fn is_boundary(&self) -> bool {
+        self.tags.get("boundary").is_some_and(|boundary| boundary == "administrative")
+    }
+
+
+    /// Return the admin level of this relation (2 for a country, 4 for a region...), if it has a valid one
+    pub fn admin_level(&self) -> Option<u8> {
+        self.tags.get("admin_level").and_then(|admin_level| admin_level.parse().ok())
+    }
+
+
+    /// Return true if this relation is a route (an ordered list of ways, as a bus line)
+    pub fn is_route(&self) -> bool {
+        self.tags.get("type").is_some_and(|relation_type| relation_type == "route")
+    }
+
+
+    /// Return the ids of the ways of this relation with one of the given roles, in order
+    pub fn way_members(&self, roles: &[&str]) -> Vec<u64> {
+        self.members.iter()
+            .filter(|member| member.member_type == MemberType::Way && roles.contains(&member.role.as_str()))
+            .map(|member| member.id)
+            .collect()
+    }
+}
+
+
+
+
+
+
+
+/// Return the way type described by the given tags (the value of the "highway", "waterway" or "railway" tag),
+/// or "" if there is none
+pub fn way_type(tags: &HashMap<String, String>) -> &str {
+    if tags.contains_key("highway") {tags["highway"].as_str()}
+    else if tags.contains_key("waterway") {tags["waterway"].as_str()}
+    else if tags.contains_key("railway") {tags["railway"].as_str()}
+    else {""}
+}
+
+
+
+
+
+
+/// OpenStreetMap elements, independently of the source they were read from (Overpass API, file)
+#[derive(Default)]
+pub struct OsmData {
+    pub nodes: Vec<OsmNode>,
+    pub ways: Vec<OsmWay>,
+    pub relations: Vec<OsmRelation>,
+}
+
+
+impl OsmData {
+
+    /// Parse the JSON returned by the Overpass API
+    pub fn from_json(data: &str) -> Result<OsmData, Error> {
+        let json_data: json::JsonValue = json::parse(data)?;
+        let mut osm_data = OsmData::default();
+
+        for element in json_data["elements"].members() {
+
+            // List the tags of the element
+            let mut tags: HashMap<String, String> = HashMap::new();
+            for entry in element["tags"].entries() {
+                tags.insert(entry.0.to_string(), entry.1.to_string());
+            }
+
+            if element["type"] == "node" {
+                osm_data.nodes.push(OsmNode {
+                    id: element["id"].as_u64().ok_or_else(|| malformed_element("node", "id"))?,
+                    lat: element["lat"].as_f64().ok_or_else(|| malformed_element("node", "lat"))?,
+                    lon: element["lon"].as_f64().ok_or_else(|| malformed_element("node", "lon"))?,
+                    tags,
+                });
+            }
+
+            else if element["type"] == "way" {
+                let mut nodes: Vec<u64> = Vec::new();
+                for node_id in element["nodes"].members() {
+                    nodes.push(node_id.as_u64().ok_or_else(|| malformed_element("way", "nodes"))?);
+                }
+
+                osm_data.ways.push(OsmWay {
+                    id: element["id"].as_u64().ok_or_else(|| malformed_element("way", "id"))?,
+                    nodes,
+                    tags,
+                });
+            }
+
+            else if element["type"] == "relation" {
+                let mut members: Vec<OsmMember> = Vec::new();
+                for member in element["members"].members() {
+                    members.push(OsmMember {
+                        member_type: member["type"].as_str().and_then(MemberType::from).ok_or_else(|| malformed_element("relation", "members"))?,
+                        id: member["ref"].as_u64().ok_or_else(|| malformed_element("relation", "members"))?,
+                        role: member["role"].as_str().unwrap_or_default().to_string(),
+                    });
+                }
+
+                osm_data.relations.push(OsmRelation {
+                    id: element["id"].as_u64().ok_or_else(|| malformed_element("relation", "id"))?,
+                    members,
+                    tags,
+                });
+            }
+        }
+
+        Ok(osm_data)
+    }
+
+
+
+    /// Return the smallest bounding box containing every node, or None if there is no node
+    pub fn extent(&self) -> Option<geo::BoundingBox> {
+        let first = self.nodes.first()?;
+        let mut coo = [first.lat, first.lon, first.lat, first.lon];
+
+        for node in &self.nodes {
+            coo = [coo[0].min(node.lat), coo[1].min(node.lon), coo[2].max(node.lat), coo[3].max(node.lon)];
+        }
+
+        Some(geo::BoundingBox::new(coo[0], coo[1], coo[2], coo[3]))
+    }
+
+
+
+    /// Add the elements of the given data to these ones
+    pub fn append(&mut self, mut other: OsmData) {
+        self.nodes.append(&mut other.nodes);
+        self.ways.append(&mut other.ways);
+        self.relations.append(&mut other.relations);
+    }
+
+
+
+    /// Join the given ways (by id) into chains of node ids: a way is appended to the previous one when they share
+    /// an end node, reversed if needed. Closed chains are the rings of a multipolygon, open ones the parts of a route.
+    /// The ways that are not in these data are skipped.
+    pub fn join_ways(&self, way_ids: &[u64]) -> Vec<Vec<u64>> {
+        let ways: HashMap<u64, &OsmWay> = self.ways.iter().map(|way| (way.id, way)).collect();
+        let mut remaining: Vec<&OsmWay> = way_ids.iter().filter_map(|way_id| ways.get(way_id).copied()).filter(|way| way.nodes.len() > 1).collect();
+        let mut chains: Vec<Vec<u64>> = Vec::new();
+
+        while !remaining.is_empty() {
+            let mut chain = remaining.remove(0).nodes.clone();
+
+            // Add the ways sharing an end with the chain, at its end or at its start, until the chain is closed or no way fits
+            while chain.first() != chain.last() {
+                let (first, last) = (chain[0], chain[chain.len() - 1]);
+                let shares_end = |way: &&OsmWay, node_id: u64| way.nodes.first() == Some(&node_id) || way.nodes.last() == Some(&node_id);
+
+                if let Some(index) = remaining.iter().position(|way| shares_end(way, last)) {
+                    let mut nodes = remaining.remove(index).nodes.clone();
+                    if nodes.first() != Some(&last) {nodes.reverse();}
+                    chain.extend(nodes.into_iter().skip(1));
+                }
+                else if let Some(index) = remaining.iter().position(|way| shares_end(way, first)) {
+                    let mut nodes = remaining.remove(index).nodes.clone();
+                    if nodes.last() != Some(&first) {nodes.reverse();}
+                    nodes.extend(chain.into_iter().skip(1));
+                    chain = nodes;
+                }
+                else {break;}
+            }
+
+            chains.push(chain);
+        }
+
+        chains
+    }
+
+
+
+    /// Return the elements needed to display the given area: the ways and relations kept by the given filter
+    /// crossing the area, their ways and nodes, and the points of interest kept by the filter inside the area.
+    pub fn filter(&self, bounding_box: &geo::BoundingBox, element_filter: &style::ElementFilter) -> OsmData {
+        let coordinates: HashMap<u64, &OsmNode> = self.nodes.iter().map(|node| (node.id, node)).collect();
+
+        // Return the coordinates of the given node relative to the bottom left corner of the bounding box
+        let relative_coordinates = |node_id: &u64| coordinates.get(node_id).map(|node| [node.lat - bounding_box.coo[0], node.lon - bounding_box.coo[1]]);
+
+        // A way crosses the area if one of its segments does, even if none of its nodes is inside
+        let crosses_area = |way: &OsmWay| way.nodes.windows(2).any(|segment| {
+            match (relative_coordinates(&segment[0]), relative_coordinates(&segment[1])) {
+                (Some(start), Some(end)) => raster::clip_segment(start, end, bounding_box.dim_deg[0], bounding_box.dim_deg[1]).is_some(),
+                _ => false,
+            }
+        });
+
+        // A filled area (a lake, a forest...) or a relation can also cover the whole area without crossing it:
+        // keep it if its extent overlaps the area
+        let overlaps_area = |way: &OsmWay| {
+            let points: Vec<[f64; 2]> = way.nodes.iter().filter_map(relative_coordinates).collect();
+            !points.is_empty()
+                && points.iter().any(|point| point[0] >= 0.0) && points.iter().any(|point| point[0] <= bounding_box.dim_deg[0])
+                && points.iter().any(|point| point[1] >= 0.0) && points.iter().any(|point| point[1] <= bounding_box.dim_deg[1])
+        };
+
+        let mut result = OsmData::default();
+        let mut kept_nodes: HashSet<u64> = HashSet::new();
+        let mut kept_ways: HashSet<u64> = HashSet::new();
+
+        for way in &self.ways {
+            if !element_filter.keeps_way(&way.tags) {continue;}
+            let is_visible = crosses_area(way) || (element_filter.keeps_area(&way.tags) && overlaps_area(way));
+            if !is_visible {continue;}
+
+            kept_nodes.extend(way.nodes.iter());
+            kept_ways.insert(way.id);
+            result.ways.push(way.clone());
+        }
+
+        // Relations are kept with all their ways, even the ones outside the area, so their rings stay closed
+        let ways: HashMap<u64, &OsmWay> = self.ways.iter().map(|way| (way.id, way)).collect();
+        for relation in &self.relations {
+            if !element_filter.keeps_relation(relation) {continue;}
+
+            let members: Vec<&OsmWay> = relation.members.iter()
+                .filter(|member| member.member_type == MemberType::Way)
+                .filter_map(|member| ways.get(&member.id).copied())
+                .collect();
+            if !members.iter().any(|way| crosses_area(way) || overlaps_area(way)) {continue;}
+
+            for way in members {
+                if !kept_ways.insert(way.id) {continue;}
+                kept_nodes.extend(way.nodes.iter());
+                result.ways.push(way.clone());
+            }
+            result.relations.push(relation.clone());
+        }
+
+        // Points of interest are kept on their own, if they are inside the area
+        let is_kept_poi = |node: &OsmNode| {
+            let coordinates = [node.lat - bounding_box.coo[0], node.lon - bounding_box.coo[1]];
+            let inside = (0.0..=bounding_box.dim_deg[0]).contains(&coordinates[0]) && (0.0..=bounding_box.dim_deg[1]).contains(&coordinates[1]);
+            inside && element_filter.keeps_node(&node.tags)
+        };
+
+        result.nodes = self.nodes.iter().filter(|node| kept_nodes.contains(&node.id) || is_kept_poi(node)).cloned().collect();
+        result
+    }
+}
+
+
+
+
+
+
+/// A source of OpenStreetMap data (the Overpass API, a file...)
+pub trait DataSource {
+
+    /// Return the elements needed to display the given area, kept by the given filter
+    fn load(&mut self, bounding_box: geo::BoundingBox, element_filter: &style::ElementFilter) -> Result<OsmData, Error>;
+
+    /// Return the area covered by the data of this source, if it is limited
+    fn bounds(&mut self) -> Result<Option<geo::BoundingBox>, Error> {
+        Ok(None)
+    }
+}
+
+
+
+
+
+
+/// Return the error raised when an element of the OSM data lacks a field or has an invalid one
+fn malformed_element(element_type: &str, field: &str) -> Error {
+    Error::MalformedJson(format!("a {} has no valid '{}' field", element_type, field))
+}
\ No newline at end of file
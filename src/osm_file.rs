@@ -0,0 +1,226 @@
+use crate::geo;
+use crate::style;
+use crate::error::Error;
+use crate::osm::{DataSource, MemberType, OsmData, OsmMember, OsmNode, OsmRelation, OsmWay};
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+
+
+/// Format of an OpenStreetMap file
+enum Format {
+    Xml,    // OSM XML (.osm), as exported from openstreetmap.org or JOSM
+    Json,   // Overpass JSON, as returned by the Overpass API
+}
+
+
+impl Format {
+
+    /// Guess the format of the file from its extension, or from its first character if the extension is unknown
+    fn guess(path: &Path, content: &str) -> Option<Format> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("osm") | Some("xml") => Some(Format::Xml),
+            Some("json") => Some(Format::Json),
+            _ => match content.trim_start().chars().next() {
+                Some('<') => Some(Format::Xml),
+                Some('{') => Some(Format::Json),
+                _ => None,
+            },
+        }
+    }
+}
+
+
+
+
+
+
+/// An OpenStreetMap file in the OSM XML or Overpass JSON format, loaded in memory.
+/// Used as a data source to render saved data without requesting the Overpass API.
+pub struct OsmFile {
+    data: OsmData,
+    bounds: Option<geo::BoundingBox>,   // Bounds given in the file (<bounds> element), if any
+}
+
+
+impl OsmFile {
+
+    /// Read and parse the given file
+    pub fn open(path: &Path) -> Result<OsmFile, Error> {
+        let content = fs::read_to_string(path).map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))?;
+
+        // Nothing was fetched: invalid JSON is an error of the file, like invalid XML
+        let in_file = |err: Error| match err {
+            Error::MalformedFile(details) | Error::MalformedJson(details) => Error::MalformedFile(format!("{}: {}", path.display(), details)),
+            err => err,
+        };
+
+        match Format::guess(path, &content) {
+            Some(Format::Xml) => OsmFile::from_xml(&content).map_err(in_file),
+            Some(Format::Json) => Ok(OsmFile {data: OsmData::from_json(&content).map_err(in_file)?, bounds: None}),
+            None => Err(Error::MalformedFile(format!("{}: unknown file format", path.display()))),
+        }
+    }
+
+
+
+    /// Parse an OSM XML document
+    fn from_xml(content: &str) -> Result<OsmFile, Error> {
+        let document = roxmltree::Document::parse(content).map_err(|err| Error::MalformedFile(err.to_string()))?;
+        let mut file = OsmFile {data: OsmData::default(), bounds: None};
+
+        for element in document.root_element().children().filter(|element| element.is_element()) {
+            match element.tag_name().name() {
+                "bounds" => {
+                    let coo = [
+                        xml_attribute(&element, "minlat")?,
+                        xml_attribute(&element, "minlon")?,
+                        xml_attribute(&element, "maxlat")?,
+                        xml_attribute(&element, "maxlon")?,
+                    ];
+                    file.bounds = Some(geo::BoundingBox::new(coo[0], coo[1], coo[2], coo[3]));
+                },
+
+                "node" => {
+                    let mut tags: HashMap<String, String> = HashMap::new();
+                    for child in element.children().filter(|child| child.tag_name().name() == "tag") {
+                        tags.insert(xml_attribute(&child, "k")?, xml_attribute(&child, "v")?);
+                    }
+
+                    file.data.nodes.push(OsmNode {
+                        id: xml_id(&element, "id")?,
+                        lat: xml_attribute(&element, "lat")?,
+                        lon: xml_attribute(&element, "lon")?,
+                        tags,
+                    });
+                },
+
+                "way" => {
+                    let mut nodes: Vec<u64> = Vec::new();
+                    let mut tags: HashMap<String, String> = HashMap::new();
+
+                    for child in element.children() {
+                        match child.tag_name().name() {
+                            "nd" => nodes.push(xml_id(&child, "ref")?),
+                            "tag" => {tags.insert(xml_attribute(&child, "k")?, xml_attribute(&child, "v")?);},
+                            _ => (),
+                        }
+                    }
+
+                    file.data.ways.push(OsmWay {id: xml_id(&element, "id")?, nodes, tags});
+                },
+
+                "relation" => {
+                    let mut members: Vec<OsmMember> = Vec::new();
+                    let mut tags: HashMap<String, String> = HashMap::new();
+
+                    for child in element.children() {
+                        match child.tag_name().name() {
+                            "member" => members.push(OsmMember {
+                                member_type: child.attribute("type").and_then(MemberType::from)
+                                    .ok_or_else(|| Error::MalformedFile(String::from("a <member> element has no valid 'type' attribute")))?,
+                                id: xml_id(&child, "ref")?,
+                                role: child.attribute("role").unwrap_or_default().to_string(),
+                            }),
+                            "tag" => {tags.insert(xml_attribute(&child, "k")?, xml_attribute(&child, "v")?);},
+                            _ => (),
+                        }
+                    }
+
+                    file.data.relations.push(OsmRelation {id: xml_id(&element, "id")?, members, tags});
+                },
+
+                _ => (),
+            }
+        }
+
+        Ok(file)
+    }
+}
+
+
+impl DataSource for OsmFile {
+    fn load(&mut self, bounding_box: geo::BoundingBox, element_filter: &style::ElementFilter) -> Result<OsmData, Error> {
+        Ok(self.data.filter(&bounding_box, element_filter))
+    }
+
+
+    fn bounds(&mut self) -> Result<Option<geo::BoundingBox>, Error> {
+        match self.bounds {
+            Some(bounds) => Ok(Some(bounds)),
+            None => Ok(self.data.extent()),
+        }
+    }
+}
+
+
+
+
+
+
+/// Return the parsed value of the given attribute of an XML element
+fn xml_attribute<T: std::str::FromStr>(element: &roxmltree::Node, name: &str) -> Result<T, Error> {
+    element.attribute(name)
+        .and_then(|value| value.parse::<T>().ok())
+        .ok_or_else(|| Error::MalformedFile(format!("a <{}> element has no valid '{}' attribute", element.tag_name().name(), name)))
+}
+
+
+
+/// Return the id given by the attribute of an XML element. The elements not uploaded yet have negative ids
+/// in the files saved by JOSM: they are mapped above 2^63 (as their two's complement), where there is no uploaded element.
+fn xml_id(element: &roxmltree::Node, name: &str) -> Result<u64, Error> {
+    match xml_attribute::<u64>(element, name) {
+        Ok(id) => Ok(id),
+        Err(err) => xml_attribute::<i64>(element, name).ok().filter(|id| *id < 0).map(|id| id as u64).ok_or(err),
+    }
+}
+
+
+
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn negative_ids_are_kept_apart() {
+        let content = r#"<osm>
+            <node id="-1" lat="0.0" lon="0.0"/>
+            <node id="1" lat="1.0" lon="1.0"/>
+            <way id="-2"><nd ref="-1"/><nd ref="1"/></way>
+            <relation id="-3"><member type="way" ref="-2" role="outer"/></relation>
+        </osm>"#;
+        let file = OsmFile::from_xml(content).ok().unwrap();
+
+        assert_eq!(file.data.nodes[0].id, u64::MAX);
+        assert_eq!(file.data.nodes[1].id, 1);
+        assert_eq!(file.data.ways[0].id, u64::MAX - 1);
+        assert_eq!(file.data.ways[0].nodes, vec![u64::MAX, 1]);
+        assert_eq!(file.data.relations[0].members[0].id, u64::MAX - 1);
+
+        assert!(OsmFile::from_xml(r#"<osm><node id="x" lat="0.0" lon="0.0"/></osm>"#).is_err());
+    }
+
+
+
+    #[test]
+    fn invalid_json_is_an_error_of_the_file() {
+        let path = std::env::temp_dir().join(format!("rustreet-{}-invalid.json", std::process::id()));
+        fs::write(&path, "garbage").unwrap();
+        let result = OsmFile::open(&path);
+        fs::remove_file(&path).unwrap();
+
+        match result {
+            Err(Error::MalformedFile(details)) => assert!(details.starts_with(&path.display().to_string()), "{}", details),
+            _ => panic!("expected an error of the file"),
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,939 @@
+use crate::geo;
+use crate::style;
+use crate::error::Error;
+use crate::osm::{DataSource, MemberType, OsmData, OsmMember, OsmNode, OsmRelation, OsmWay};
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+use flate2::read::ZlibDecoder;
+
+
+
+/// Maximum size of a blob header, as specified by the PBF format
+const MAX_BLOB_HEADER_SIZE: usize = 64 * 1024;
+
+/// Maximum size of a blob, as specified by the PBF format
+const MAX_BLOB_SIZE: usize = 32 * 1024 * 1024;
+
+/// Coordinates are stored in nanodegrees
+const NANODEGREE: f64 = 0.000000001;
+
+/// Zoom factor applied to the requested area to get the area whose nodes are read: the ways crossing the requested area
+/// without any node inside it are kept if one of their nodes is in this larger area
+const NODE_AREA_ZOOM: f64 = 1.0 / 3.0;
+
+
+
+
+
+
+/// An OpenStreetMap PBF extract (.osm.pbf file), used as a data source to work without network.
+///
+/// The whole file is read when data is requested, but only the elements of the requested area are kept in memory,
+/// so the file is only read again when other elements (of another area, level of details or layers) are requested.
+pub struct PbfFile {
+    path: PathBuf,
+    bounds: Option<geo::BoundingBox>,   // Bounding box given in the header of the file, if any
+    loaded: Option<(style::ElementFilter, geo::BoundingBox, OsmData)>,  // Elements of an area kept by a filter
+}
+
+
+impl PbfFile {
+
+    /// Open the given PBF file and read its header
+    pub fn open(path: &Path) -> Result<PbfFile, Error> {
+        let mut file = PbfFile {path: path.to_path_buf(), bounds: None, loaded: None};
+
+        let opened = File::open(path).map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))?;
+        let mut reader = BufReader::new(opened);
+        match read_blob(&mut reader)? {
+            Some((blob_type, data)) if blob_type == "OSMHeader" => file.bounds = read_header_bbox(&data)?,
+            _ => return Err(Error::MalformedFile(String::from("the file does not start with an OSMHeader block"))),
+        }
+
+        Ok(file)
+    }
+
+
+
+    /// Call the given function on each data block of the file, in order
+    fn for_each_block(&self, mut f: impl FnMut(&PrimitiveBlock) -> Result<(), Error>) -> Result<(), Error> {
+        let mut reader = BufReader::new(File::open(&self.path)?);
+
+        while let Some((blob_type, data)) = read_blob(&mut reader)? {
+            if blob_type != "OSMData" {continue;}
+            f(&PrimitiveBlock::parse(&data)?)?;
+        }
+
+        Ok(())
+    }
+
+
+
+    /// Read the elements of the given area kept by the given filter from the file: the ways with a node inside the area
+    /// (or near it, see NODE_AREA_ZOOM), the relations with such a way (with all their ways), their nodes,
+    /// and the points of interest of the area.
+    /// The file is read three times: once to find the relations and the nodes inside the area, once to find the ways
+    /// (the ones kept and the ones of the relations), then once to find the nodes of the ways outside the area.
+    fn read_elements(&self, bounding_box: &geo::BoundingBox, element_filter: &style::ElementFilter) -> Result<OsmData, Error> {
+        let mut data = OsmData::default();
+        let mut inside_nodes: HashMap<u64, OsmNode> = HashMap::new();
+
+        let mut node_area = *bounding_box;
+        node_area.zoom(NODE_AREA_ZOOM);
+
+        self.for_each_block(|block| {
+            for relation in block.relations()? {
+                if element_filter.keeps_relation(&relation) {data.relations.push(relation);}
+            }
+            for node in block.nodes(|node| node_area.contains_point([node.lat, node.lon]))? {
+                inside_nodes.insert(node.id, node);
+            }
+            Ok(())
+        })?;
+
+        let relation_ways: HashSet<u64> = data.relations.iter()
+            .flat_map(|relation| relation.members.iter())
+            .filter(|member| member.member_type == MemberType::Way)
+            .map(|member| member.id)
+            .collect();
+
+        // The ways of the relations are kept even outside the area, so their rings stay closed
+        self.for_each_block(|block| {
+            for way in block.ways()? {
+                let is_inside = way.nodes.iter().any(|node_id| inside_nodes.contains_key(node_id));
+                if (is_inside && element_filter.keeps_way(&way.tags)) || relation_ways.contains(&way.id) {data.ways.push(way);}
+            }
+            Ok(())
+        })?;
+
+        // Only keep the relations with a member inside the area, and the ways that are inside or belong to one of them
+        let inside_ways: HashSet<u64> = data.ways.iter()
+            .filter(|way| way.nodes.iter().any(|node_id| inside_nodes.contains_key(node_id)))
+            .map(|way| way.id)
+            .collect();
+        data.relations.retain(|relation| relation.members.iter().any(|member| match member.member_type {
+            MemberType::Way => inside_ways.contains(&member.id),
+            MemberType::Node => inside_nodes.contains_key(&member.id),
+            MemberType::Relation => false,
+        }));
+
+        let kept_relation_ways: HashSet<u64> = data.relations.iter()
+            .flat_map(|relation| relation.members.iter())
+            .filter(|member| member.member_type == MemberType::Way)
+            .map(|member| member.id)
+            .collect();
+        data.ways.retain(|way| (inside_ways.contains(&way.id) && element_filter.keeps_way(&way.tags)) || kept_relation_ways.contains(&way.id));
+
+        // Keep the nodes of the ways inside the area and the points of interest, then read the nodes of the ways outside of it
+        let mut needed_nodes: HashSet<u64> = data.ways.iter().flat_map(|way| way.nodes.iter().copied()).collect();
+        for (node_id, node) in inside_nodes {
+            if needed_nodes.remove(&node_id) || element_filter.keeps_node(&node.tags) {data.nodes.push(node);}
+        }
+
+        if !needed_nodes.is_empty() {
+            self.for_each_block(|block| {
+                data.nodes.append(&mut block.nodes(|node| needed_nodes.contains(&node.id))?);
+                Ok(())
+            })?;
+        }
+
+        Ok(data)
+    }
+}
+
+
+impl DataSource for PbfFile {
+    fn load(&mut self, bounding_box: geo::BoundingBox, element_filter: &style::ElementFilter) -> Result<OsmData, Error> {
+
+        // Read the file only if those elements are not loaded yet
+        let is_loaded = matches!(&self.loaded, Some((loaded_filter, loaded_box, _)) if loaded_filter == element_filter && loaded_box.contains(&bounding_box));
+        if !is_loaded {
+            self.loaded = Some((element_filter.clone(), bounding_box, self.read_elements(&bounding_box, element_filter)?));
+        }
+
+        match &self.loaded {
+            Some((_, _, data)) => Ok(data.filter(&bounding_box, element_filter)),
+            None => Ok(OsmData::default()),
+        }
+    }
+
+
+    fn bounds(&mut self) -> Result<Option<geo::BoundingBox>, Error> {
+        if self.bounds.is_some() {return Ok(self.bounds);}
+
+        // Without a bounding box in the header, use the extent of the nodes
+        let mut extent: Option<[f64; 4]> = None;
+        self.for_each_block(|block| {
+            for node in block.nodes(|_| true)? {
+                extent = Some(match extent {
+                    Some(coo) => [coo[0].min(node.lat), coo[1].min(node.lon), coo[2].max(node.lat), coo[3].max(node.lon)],
+                    None => [node.lat, node.lon, node.lat, node.lon],
+                });
+            }
+            Ok(())
+        })?;
+
+        Ok(extent.map(|coo| geo::BoundingBox::new(coo[0], coo[1], coo[2], coo[3])))
+    }
+}
+
+
+
+
+
+
+/// Read the next blob of the file. Return its type and its decompressed content, or None at the end of the file.
+fn read_blob(reader: &mut impl Read) -> Result<Option<(String, Vec<u8>)>, Error> {
+
+    // Each blob is preceded by the size of its header, as a big-endian int32
+    // The file ends before a blob, not in the middle of its size
+    let mut size_bytes = [0u8; 4];
+    if reader.read(&mut size_bytes[..1])? == 0 {return Ok(None);}
+    read_exact(reader, &mut size_bytes[1..])?;
+
+    let header_size = u32::from_be_bytes(size_bytes) as usize;
+    if header_size > MAX_BLOB_HEADER_SIZE {return Err(malformed("blob header too large"));}
+
+    // Read the blob header: the type of the blob and its size
+    let mut header = vec![0u8; header_size];
+    read_exact(reader, &mut header)?;
+
+    let mut blob_type = String::new();
+    let mut blob_size: usize = 0;
+
+    let mut fields = ProtoReader::new(&header);
+    while let Some((number, field)) = fields.next_field()? {
+        match (number, field) {
+            (1, Field::Bytes(value)) => blob_type = String::from_utf8_lossy(value).to_string(),
+            (3, Field::Varint(value)) => blob_size = value as usize,
+            _ => (),
+        }
+    }
+
+    if blob_size > MAX_BLOB_SIZE {return Err(malformed("blob too large"));}
+
+    // Read the blob itself, and decompress its content
+    let mut blob = vec![0u8; blob_size];
+    read_exact(reader, &mut blob)?;
+
+    // Size of the decompressed content, given before the compressed data
+    let mut raw_size: usize = MAX_BLOB_SIZE;
+
+    let mut fields = ProtoReader::new(&blob);
+    while let Some((number, field)) = fields.next_field()? {
+        match (number, field) {
+            (1, Field::Bytes(raw)) => return Ok(Some((blob_type, raw.to_vec()))),
+            (2, Field::Varint(value)) => {
+                if value as usize > MAX_BLOB_SIZE {return Err(malformed("uncompressed blob too large"));}
+                raw_size = value as usize;
+            },
+            (3, Field::Bytes(zlib_data)) => {
+                // Don't decompress more than announced, so a corrupted blob can't fill the memory
+                let mut data: Vec<u8> = Vec::with_capacity(raw_size);
+                ZlibDecoder::new(zlib_data).take(raw_size as u64 + 1).read_to_end(&mut data).map_err(|_| malformed("corrupted zlib data"))?;
+                if data.len() > raw_size {return Err(malformed("uncompressed blob larger than announced"));}
+                return Ok(Some((blob_type, data)));
+            },
+            (4, _) | (5, _) | (6, _) | (7, _) => return Err(malformed("only uncompressed and zlib-compressed blobs are supported")),
+            _ => (),
+        }
+    }
+
+    Err(malformed("blob without data"))
+}
+
+
+
+/// Fill the given buffer from the reader. The end of the file is an error, as the buffer is a part of a blob.
+fn read_exact(reader: &mut impl Read, buffer: &mut [u8]) -> Result<(), Error> {
+    match reader.read_exact(buffer) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Err(malformed("truncated blob")),
+        Err(err) => Err(err.into()),
+    }
+}
+
+
+
+/// Return the bounding box stored in the given header block, if any
+fn read_header_bbox(data: &[u8]) -> Result<Option<geo::BoundingBox>, Error> {
+    let mut fields = ProtoReader::new(data);
+
+    while let Some((number, field)) = fields.next_field()? {
+        if let (1, Field::Bytes(bbox_data)) = (number, field) {
+
+            // left, right, top, bottom, in nanodegrees
+            let mut sides = [0i64; 4];
+            let mut bbox_fields = ProtoReader::new(bbox_data);
+            while let Some((number, field)) = bbox_fields.next_field()? {
+                if let (1..=4, Field::Varint(value)) = (number, field) {
+                    sides[number as usize - 1] = zigzag(value);
+                }
+            }
+
+            let [left, right, top, bottom] = sides.map(|side| side as f64 * NANODEGREE);
+            return Ok(Some(geo::BoundingBox::new(bottom, left, top, right)));
+        }
+    }
+
+    Ok(None)
+}
+
+
+
+
+
+
+/// A block of OpenStreetMap elements, sharing a table of strings and an encoding of the coordinates
+struct PrimitiveBlock<'a> {
+    strings: Vec<String>,
+    groups: Vec<&'a [u8]>,
+    granularity: i64,
+    lat_offset: i64,
+    lon_offset: i64,
+}
+
+
+impl<'a> PrimitiveBlock<'a> {
+
+    /// Parse the given decompressed block. The groups of elements are only decoded when needed.
+    fn parse(data: &'a [u8]) -> Result<PrimitiveBlock<'a>, Error> {
+        let mut block = PrimitiveBlock {strings: Vec::new(), groups: Vec::new(), granularity: 100, lat_offset: 0, lon_offset: 0};
+
+        let mut fields = ProtoReader::new(data);
+        while let Some((number, field)) = fields.next_field()? {
+            match (number, field) {
+                (1, Field::Bytes(table)) => {
+                    let mut strings = ProtoReader::new(table);
+                    while let Some((_, string)) = strings.next_field()? {
+                        if let Field::Bytes(string) = string {
+                            block.strings.push(String::from_utf8_lossy(string).to_string());
+                        }
+                    }
+                },
+                (2, Field::Bytes(group)) => block.groups.push(group),
+                (17, Field::Varint(value)) => block.granularity = value as i64,
+                (19, Field::Varint(value)) => block.lat_offset = value as i64,
+                (20, Field::Varint(value)) => block.lon_offset = value as i64,
+                _ => (),
+            }
+        }
+
+        Ok(block)
+    }
+
+
+
+    /// Return the string of the given index in the string table
+    fn string(&self, index: u64) -> String {
+        self.strings.get(index as usize).cloned().unwrap_or_default()
+    }
+
+
+
+    /// Return the tags built from the given lists of key and value indexes
+    fn tags(&self, keys: &[u64], values: &[u64]) -> HashMap<String, String> {
+        keys.iter().zip(values).map(|(key, value)| (self.string(*key), self.string(*value))).collect()
+    }
+
+
+
+    /// Return the coordinate in degrees of the given encoded latitude or longitude
+    fn coordinate(&self, offset: i64, value: i64) -> Result<f64, Error> {
+        let nanodegrees = self.granularity.checked_mul(value).and_then(|value| offset.checked_add(value)).ok_or_else(|| malformed("coordinate overflow"))?;
+        Ok(nanodegrees as f64 * NANODEGREE)
+    }
+
+
+
+    /// Return every way of the block
+    fn ways(&self) -> Result<Vec<OsmWay>, Error> {
+        let mut ways: Vec<OsmWay> = Vec::new();
+
+        for group in &self.groups {
+            let mut fields = ProtoReader::new(group);
+            while let Some((number, field)) = fields.next_field()? {
+                if let (3, Field::Bytes(way_data)) = (number, field) {
+                    ways.push(self.parse_way(way_data)?);
+                }
+            }
+        }
+
+        Ok(ways)
+    }
+
+
+
+    /// Return every relation of the block
+    fn relations(&self) -> Result<Vec<OsmRelation>, Error> {
+        let mut relations: Vec<OsmRelation> = Vec::new();
+
+        for group in &self.groups {
+            let mut fields = ProtoReader::new(group);
+            while let Some((number, field)) = fields.next_field()? {
+                if let (4, Field::Bytes(relation_data)) = (number, field) {
+                    relations.push(self.parse_relation(relation_data)?);
+                }
+            }
+        }
+
+        Ok(relations)
+    }
+
+
+
+    /// Return the nodes of the block accepted by the given function
+    fn nodes(&self, keep: impl Fn(&OsmNode) -> bool) -> Result<Vec<OsmNode>, Error> {
+        let mut nodes: Vec<OsmNode> = Vec::new();
+
+        for group in &self.groups {
+            let mut fields = ProtoReader::new(group);
+            while let Some((number, field)) = fields.next_field()? {
+                match (number, field) {
+                    (1, Field::Bytes(node_data)) => {
+                        let node = self.parse_node(node_data)?;
+                        if keep(&node) {nodes.push(node);}
+                    },
+                    (2, Field::Bytes(dense_data)) => self.parse_dense_nodes(dense_data, &keep, &mut nodes)?,
+                    _ => (),
+                }
+            }
+        }
+
+        Ok(nodes)
+    }
+
+
+
+    /// Parse a way message
+    fn parse_way(&self, data: &[u8]) -> Result<OsmWay, Error> {
+        let mut id: u64 = 0;
+        let mut keys: Vec<u64> = Vec::new();
+        let mut values: Vec<u64> = Vec::new();
+        let mut refs: Vec<u64> = Vec::new();
+
+        let mut fields = ProtoReader::new(data);
+        while let Some((number, field)) = fields.next_field()? {
+            match (number, field) {
+                (1, Field::Varint(value)) => id = value,
+                (2, field) => field.append_varints(&mut keys)?,
+                (3, field) => field.append_varints(&mut values)?,
+                (8, field) => field.append_varints(&mut refs)?,
+                _ => (),
+            }
+        }
+
+        // Node ids are delta-encoded
+        let mut node_id: i64 = 0;
+        let mut nodes: Vec<u64> = Vec::new();
+        for delta in refs {
+            node_id = add_delta(node_id, delta)?;
+            nodes.push(node_id as u64);
+        }
+
+        Ok(OsmWay {id, nodes, tags: self.tags(&keys, &values)})
+    }
+
+
+
+    /// Parse a relation message
+    fn parse_relation(&self, data: &[u8]) -> Result<OsmRelation, Error> {
+        let mut id: u64 = 0;
+        let mut keys: Vec<u64> = Vec::new();
+        let mut values: Vec<u64> = Vec::new();
+        let mut roles: Vec<u64> = Vec::new();
+        let mut member_ids: Vec<u64> = Vec::new();
+        let mut types: Vec<u64> = Vec::new();
+
+        let mut fields = ProtoReader::new(data);
+        while let Some((number, field)) = fields.next_field()? {
+            match (number, field) {
+                (1, Field::Varint(value)) => id = value,
+                (2, field) => field.append_varints(&mut keys)?,
+                (3, field) => field.append_varints(&mut values)?,
+                (8, field) => field.append_varints(&mut roles)?,
+                (9, field) => field.append_varints(&mut member_ids)?,
+                (10, field) => field.append_varints(&mut types)?,
+                _ => (),
+            }
+        }
+
+        if roles.len() != member_ids.len() || types.len() != member_ids.len() {
+            return Err(malformed("relation with inconsistent lengths"));
+        }
+
+        // Member ids are delta-encoded
+        let mut member_id: i64 = 0;
+        let mut members: Vec<OsmMember> = Vec::new();
+        for i in 0..member_ids.len() {
+            member_id = add_delta(member_id, member_ids[i])?;
+
+            let member_type = match types[i] {
+                0 => MemberType::Node,
+                1 => MemberType::Way,
+                2 => MemberType::Relation,
+                _ => return Err(malformed("unknown relation member type")),
+            };
+            members.push(OsmMember {member_type, id: member_id as u64, role: self.string(roles[i])});
+        }
+
+        Ok(OsmRelation {id, members, tags: self.tags(&keys, &values)})
+    }
+
+
+
+    /// Parse a (non-dense) node message
+    fn parse_node(&self, data: &[u8]) -> Result<OsmNode, Error> {
+        let mut id: i64 = 0;
+        let mut lat: i64 = 0;
+        let mut lon: i64 = 0;
+        let mut keys: Vec<u64> = Vec::new();
+        let mut values: Vec<u64> = Vec::new();
+
+        let mut fields = ProtoReader::new(data);
+        while let Some((number, field)) = fields.next_field()? {
+            match (number, field) {
+                (1, Field::Varint(value)) => id = zigzag(value),
+                (2, field) => field.append_varints(&mut keys)?,
+                (3, field) => field.append_varints(&mut values)?,
+                (8, Field::Varint(value)) => lat = zigzag(value),
+                (9, Field::Varint(value)) => lon = zigzag(value),
+                _ => (),
+            }
+        }
+
+        Ok(OsmNode {
+            id: id as u64,
+            lat: self.coordinate(self.lat_offset, lat)?,
+            lon: self.coordinate(self.lon_offset, lon)?,
+            tags: self.tags(&keys, &values),
+        })
+    }
+
+
+
+    /// Parse a dense nodes message, and push the nodes accepted by the given function to the vector
+    fn parse_dense_nodes(&self, data: &[u8], keep: &impl Fn(&OsmNode) -> bool, nodes: &mut Vec<OsmNode>) -> Result<(), Error> {
+        let mut ids: Vec<u64> = Vec::new();
+        let mut lats: Vec<u64> = Vec::new();
+        let mut lons: Vec<u64> = Vec::new();
+        let mut keys_vals: Vec<u64> = Vec::new();
+
+        let mut fields = ProtoReader::new(data);
+        while let Some((number, field)) = fields.next_field()? {
+            match number {
+                1 => field.append_varints(&mut ids)?,
+                8 => field.append_varints(&mut lats)?,
+                9 => field.append_varints(&mut lons)?,
+                10 => field.append_varints(&mut keys_vals)?,
+                _ => (),
+            }
+        }
+
+        if lats.len() != ids.len() || lons.len() != ids.len() {
+            return Err(malformed("dense nodes with inconsistent lengths"));
+        }
+
+        // Ids and coordinates are delta-encoded
+        let mut id: i64 = 0;
+        let mut lat: i64 = 0;
+        let mut lon: i64 = 0;
+
+        // Tags of all the nodes are stored as (key, value) string indexes, each node ending with a 0
+        let mut tag_groups = keys_vals.split(|index| *index == 0);
+
+        for i in 0..ids.len() {
+            id = add_delta(id, ids[i])?;
+            lat = add_delta(lat, lats[i])?;
+            lon = add_delta(lon, lons[i])?;
+
+            let tag_indexes = tag_groups.next().unwrap_or_default();
+            let keys: Vec<u64> = tag_indexes.iter().step_by(2).copied().collect();
+            let values: Vec<u64> = tag_indexes.iter().skip(1).step_by(2).copied().collect();
+
+            let node = OsmNode {
+                id: id as u64,
+                lat: self.coordinate(self.lat_offset, lat)?,
+                lon: self.coordinate(self.lon_offset, lon)?,
+                tags: self.tags(&keys, &values),
+            };
+
+            if keep(&node) {nodes.push(node);}
+        }
+
+        Ok(())
+    }
+}
+
+
+
+
+
+
+/// Value of a field of a protobuf message
+enum Field<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,          // 32 or 64 bits values, not used by the PBF format
+}
+
+
+impl<'a> Field<'a> {
+
+    /// Append the varint(s) of this field to the vector. Repeated fields can either be packed
+    /// in a single length-delimited field, or stored as one field per value.
+    fn append_varints(&self, values: &mut Vec<u64>) -> Result<(), Error> {
+        match self {
+            Field::Varint(value) => values.push(*value),
+            Field::Bytes(data) => {
+                let mut reader = ProtoReader::new(data);
+                while !reader.is_empty() {values.push(reader.varint()?);}
+            },
+            Field::Fixed => (),
+        }
+        Ok(())
+    }
+}
+
+
+
+/// Minimal reader of protobuf messages, iterating over their fields
+struct ProtoReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+
+impl<'a> ProtoReader<'a> {
+
+    fn new(data: &'a [u8]) -> ProtoReader<'a> {
+        ProtoReader {data, pos: 0}
+    }
+
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+
+    /// Read a varint
+    fn varint(&mut self) -> Result<u64, Error> {
+        let mut value: u64 = 0;
+
+        for shift in (0..64).step_by(7) {
+            let byte = *self.data.get(self.pos).ok_or_else(|| malformed("truncated varint"))?;
+            self.pos += 1;
+
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {return Ok(value);}
+        }
+
+        Err(malformed("varint too long"))
+    }
+
+
+    /// Read the given number of bytes
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], Error> {
+        let end = self.pos.checked_add(length).filter(|end| *end <= self.data.len()).ok_or_else(|| malformed("truncated field"))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+
+    /// Read the next field of the message, and return its number and value
+    fn next_field(&mut self) -> Result<Option<(u32, Field<'a>)>, Error> {
+        if self.is_empty() {return Ok(None);}
+
+        let key = self.varint()?;
+        let number = (key >> 3) as u32;
+
+        let field = match key & 0x7 {
+            0 => Field::Varint(self.varint()?),
+            1 => {self.bytes(8)?; Field::Fixed},
+            2 => {
+                let length = self.varint()? as usize;
+                Field::Bytes(self.bytes(length)?)
+            },
+            5 => {self.bytes(4)?; Field::Fixed},
+            _ => return Err(malformed("unsupported protobuf wire type")),
+        };
+
+        Ok(Some((number, field)))
+    }
+}
+
+
+
+/// Decode a zigzag-encoded signed integer
+fn zigzag(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+
+
+/// Return the given sum of delta-encoded values plus the given zigzag-encoded delta, or an error if it overflows
+fn add_delta(sum: i64, delta: u64) -> Result<i64, Error> {
+    sum.checked_add(zigzag(delta)).ok_or_else(|| malformed("delta overflow"))
+}
+
+
+
+/// Return the error raised when the file is not a valid PBF file
+fn malformed(details: &str) -> Error {
+    Error::MalformedFile(format!("invalid PBF data ({})", details))
+}
+
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+
+    /// Encode a varint
+    fn varint(mut value: u64) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::new();
+        while value >= 0x80 {
+            bytes.push((value & 0x7f) as u8 | 0x80);
+            value >>= 7;
+        }
+        bytes.push(value as u8);
+        bytes
+    }
+
+
+    /// Zigzag-encode a signed integer
+    fn zigzag_encode(value: i64) -> u64 {
+        ((value << 1) ^ (value >> 63)) as u64
+    }
+
+
+    /// Encode a varint field
+    fn varint_field(number: u32, value: u64) -> Vec<u8> {
+        let mut bytes = varint((number as u64) << 3);
+        bytes.extend(varint(value));
+        bytes
+    }
+
+
+    /// Encode a length-delimited field
+    fn bytes_field(number: u32, data: &[u8]) -> Vec<u8> {
+        let mut bytes = varint(((number as u64) << 3) | 2);
+        bytes.extend(varint(data.len() as u64));
+        bytes.extend(data);
+        bytes
+    }
+
+
+    /// Encode a packed field of delta-encoded signed integers
+    fn delta_field(number: u32, values: &[i64]) -> Vec<u8> {
+        let mut previous: i64 = 0;
+        let packed: Vec<u8> = values.iter().flat_map(|value| {
+            let delta = value - previous;
+            previous = *value;
+            varint(zigzag_encode(delta))
+        }).collect();
+        bytes_field(number, &packed)
+    }
+
+
+    /// Encode a blob (its header size, its header and the blob itself) of the given type and content
+    fn blob(blob_type: &str, content: &[u8]) -> Vec<u8> {
+        let header = [bytes_field(1, blob_type.as_bytes()), varint_field(3, content.len() as u64)].concat();
+        [(header.len() as u32).to_be_bytes().to_vec(), header, content.to_vec()].concat()
+    }
+
+
+    /// Compress the given data with zlib
+    fn zlib(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+
+    fn is_malformed<T>(result: Result<T, Error>) -> bool {
+        matches!(result, Err(Error::MalformedFile(_)))
+    }
+
+
+    /// A primitive block with 3 dense nodes, the second one being a fuel station
+    fn dense_block(ids: &[i64], lats: &[i64], lons: &[i64]) -> Vec<u8> {
+        let strings = [bytes_field(1, b""), bytes_field(1, b"amenity"), bytes_field(1, b"fuel")].concat();
+        let keys_vals: Vec<u8> = [0u64, 1, 2, 0, 0].iter().flat_map(|index| varint(*index)).collect();
+        let dense = [delta_field(1, ids), delta_field(8, lats), delta_field(9, lons), bytes_field(10, &keys_vals)].concat();
+        [bytes_field(1, &strings), bytes_field(2, &bytes_field(2, &dense))].concat()
+    }
+
+
+
+    #[test]
+    fn varint_edge_cases() {
+        assert_eq!(ProtoReader::new(&[0x00]).varint().unwrap(), 0);
+        assert_eq!(ProtoReader::new(&[0x7f]).varint().unwrap(), 127);
+        assert_eq!(ProtoReader::new(&[0x80, 0x01]).varint().unwrap(), 128);
+        assert_eq!(ProtoReader::new(&varint(u64::MAX)).varint().unwrap(), u64::MAX);
+        assert_eq!(varint(u64::MAX).len(), 10);
+
+        assert!(is_malformed(ProtoReader::new(&[]).varint()));
+        assert!(is_malformed(ProtoReader::new(&[0x80]).varint()));
+        assert!(is_malformed(ProtoReader::new(&[0xff; 11]).varint()));
+    }
+
+
+    #[test]
+    fn zigzag_edge_cases() {
+        assert_eq!(zigzag(0), 0);
+        assert_eq!(zigzag(1), -1);
+        assert_eq!(zigzag(2), 1);
+        assert_eq!(zigzag(3), -2);
+        assert_eq!(zigzag(u64::MAX - 1), i64::MAX);
+        assert_eq!(zigzag(u64::MAX), i64::MIN);
+
+        for value in [0, 1, -1, 1234567, -1234567, i64::MAX, i64::MIN] {
+            assert_eq!(zigzag(zigzag_encode(value)), value);
+        }
+    }
+
+
+    #[test]
+    fn dense_nodes_round_trip() {
+        let ids = [1000, 1002, 999];
+        let lats = [457_800_000, 457_812_345, 457_799_999];
+        let lons = [18_097_000, 18_090_000, 18_100_001];
+        let data = dense_block(&ids, &lats, &lons);
+
+        let block = PrimitiveBlock::parse(&data).unwrap();
+        let nodes = block.nodes(|_| true).unwrap();
+
+        assert_eq!(nodes.iter().map(|node| node.id).collect::<Vec<u64>>(), vec![1000, 1002, 999]);
+        for (i, node) in nodes.iter().enumerate() {
+            assert!((node.lat - lats[i] as f64 * 100.0 * NANODEGREE).abs() < 1e-9);
+            assert!((node.lon - lons[i] as f64 * 100.0 * NANODEGREE).abs() < 1e-9);
+        }
+        assert!(nodes[0].tags.is_empty());
+        assert_eq!(nodes[1].tags.get("amenity").map(String::as_str), Some("fuel"));
+        assert!(nodes[2].tags.is_empty());
+
+        // The filter is applied to the decoded nodes
+        let kept = block.nodes(|node| node.id < 1001).unwrap();
+        assert_eq!(kept.iter().map(|node| node.id).collect::<Vec<u64>>(), vec![1000, 999]);
+    }
+
+
+    #[test]
+    fn way_node_refs_are_delta_decoded() {
+        let way = [varint_field(1, 42), delta_field(8, &[10, 7, 300, 300])].concat();
+        let data = bytes_field(2, &bytes_field(3, &way));
+
+        let ways = PrimitiveBlock::parse(&data).unwrap().ways().unwrap();
+        assert_eq!(ways.len(), 1);
+        assert_eq!(ways[0].id, 42);
+        assert_eq!(ways[0].nodes, vec![10, 7, 300, 300]);
+    }
+
+
+    #[test]
+    fn malformed_messages() {
+        // Length-delimited field longer than the message
+        assert!(is_malformed(ProtoReader::new(&[0x0a, 0x05, 0x01]).next_field()));
+        // Unsupported wire type (start group)
+        assert!(is_malformed(ProtoReader::new(&[0x0b]).next_field()));
+        // Dense nodes with more ids than coordinates
+        let dense = [delta_field(1, &[1, 2]), delta_field(8, &[1]), delta_field(9, &[1])].concat();
+        let data = bytes_field(2, &bytes_field(2, &dense));
+        assert!(is_malformed(PrimitiveBlock::parse(&data).unwrap().nodes(|_| true)));
+    }
+
+
+    #[test]
+    fn overflowing_values_are_malformed() {
+        let packed = |values: &[u64]| values.iter().flat_map(|value| varint(*value)).collect::<Vec<u8>>();
+
+        // Node ids: i64::MAX, then a delta of 1
+        let dense = [bytes_field(1, &packed(&[u64::MAX - 1, 2])), bytes_field(8, &packed(&[0, 0])), bytes_field(9, &packed(&[0, 0]))].concat();
+        let data = bytes_field(2, &bytes_field(2, &dense));
+        assert!(is_malformed(PrimitiveBlock::parse(&data).unwrap().nodes(|_| true)));
+
+        // Latitude i64::MAX, multiplied by the granularity
+        let dense = [bytes_field(1, &packed(&[2])), bytes_field(8, &packed(&[u64::MAX - 1])), bytes_field(9, &packed(&[0]))].concat();
+        let data = bytes_field(2, &bytes_field(2, &dense));
+        assert!(is_malformed(PrimitiveBlock::parse(&data).unwrap().nodes(|_| true)));
+
+        // Way node refs: i64::MIN, then a delta of -1
+        let way = [varint_field(1, 42), bytes_field(8, &packed(&[u64::MAX, 1]))].concat();
+        let data = bytes_field(2, &bytes_field(3, &way));
+        assert!(is_malformed(PrimitiveBlock::parse(&data).unwrap().ways()));
+
+        // Relation member ids: i64::MAX, then a delta of 1
+        let relation = [varint_field(1, 7), bytes_field(8, &packed(&[0, 0])), bytes_field(9, &packed(&[u64::MAX - 1, 2])), bytes_field(10, &packed(&[0, 0]))].concat();
+        let data = bytes_field(2, &bytes_field(4, &relation));
+        assert!(is_malformed(PrimitiveBlock::parse(&data).unwrap().relations()));
+    }
+
+
+    #[test]
+    fn truncated_blocks_do_not_panic() {
+        let data = dense_block(&[1, 2, 3], &[10, 20, 30], &[40, 50, 60]);
+
+        for end in 0..data.len() {
+            if let Ok(block) = PrimitiveBlock::parse(&data[..end]) {
+                let _ = block.nodes(|_| true);
+                let _ = block.ways();
+                let _ = block.relations();
+            }
+        }
+    }
+
+
+    #[test]
+    fn read_compressed_blob() {
+        let content = dense_block(&[1], &[10], &[20]);
+        let zlib_blob = [varint_field(2, content.len() as u64), bytes_field(3, &zlib(&content))].concat();
+        let file = blob("OSMData", &zlib_blob);
+
+        let (blob_type, data) = read_blob(&mut file.as_slice()).unwrap().unwrap();
+        assert_eq!(blob_type, "OSMData");
+        assert_eq!(data, content);
+
+        // End of the file
+        assert!(read_blob(&mut [].as_slice()).unwrap().is_none());
+    }
+
+
+    #[test]
+    fn malformed_blobs() {
+        let content = vec![0u8; 1000];
+        let file = blob("OSMData", &[varint_field(2, content.len() as u64), bytes_field(3, &zlib(&content))].concat());
+
+        // Truncated file
+        for end in 1..file.len() {
+            assert!(is_malformed(read_blob(&mut &file[..end])));
+        }
+
+        // Content larger than announced
+        let too_large = blob("OSMData", &[varint_field(2, 10), bytes_field(3, &zlib(&content))].concat());
+        assert!(is_malformed(read_blob(&mut too_large.as_slice())));
+
+        // Announced size larger than allowed
+        let too_large = blob("OSMData", &[varint_field(2, MAX_BLOB_SIZE as u64 + 1), bytes_field(3, &zlib(&content))].concat());
+        assert!(is_malformed(read_blob(&mut too_large.as_slice())));
+
+        // Corrupted compressed data
+        let corrupted = blob("OSMData", &[varint_field(2, 1000), bytes_field(3, &[0x78, 0x9c, 0xff, 0xff, 0xff])].concat());
+        assert!(is_malformed(read_blob(&mut corrupted.as_slice())));
+
+        // Header size larger than allowed
+        let large_header = (MAX_BLOB_HEADER_SIZE as u32 + 1).to_be_bytes();
+        assert!(is_malformed(read_blob(&mut large_header.as_slice())));
+    }
+}
\ No newline at end of file
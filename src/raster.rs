@@ -0,0 +1,281 @@
+/// Clip the segment [start, end] to the rectangle [0, height] x [0, width] (Liang-Barsky algorithm).
+/// Points are (row, column) coordinates. Return the part of the segment inside the rectangle, or None if
+/// the segment is entirely outside of it.
+pub fn clip_segment(start: [f64; 2], end: [f64; 2], height: f64, width: f64) -> Option<([f64; 2], [f64; 2])> {
+    let delta = [end[0] - start[0], end[1] - start[1]];
+
+    // Parameters of the clipped segment: start + t * delta, with t in [t_min, t_max]
+    let mut t_min: f64 = 0.0;
+    let mut t_max: f64 = 1.0;
+
+    // For each side of the rectangle: (p, q) such that the point is inside when t * p <= q
+    let sides = [
+        (-delta[0], start[0]),              // row >= 0
+        (delta[0], height - start[0]),      // row <= height
+        (-delta[1], start[1]),              // column >= 0
+        (delta[1], width - start[1]),       // column <= width
+    ];
+
+    for (p, q) in sides {
+        if p == 0.0 {
+            // Parallel to this side: either always outside or always inside
+            if q < 0.0 {return None;}
+        }
+        else {
+            let t = q / p;
+            if p < 0.0 {t_min = t_min.max(t);}
+            else {t_max = t_max.min(t);}
+        }
+    }
+
+    if t_min > t_max {return None;}
+
+    Some((
+        [start[0] + t_min * delta[0], start[1] + t_min * delta[1]],
+        [start[0] + t_max * delta[0], start[1] + t_max * delta[1]],
+    ))
+}
+
+
+
+/// Return every cell of a grid crossed by the line between the 2 given cells (Bresenham's algorithm),
+/// including both ends. Consecutive cells are adjacent horizontally, vertically or diagonally.
+pub fn line_cells(start: [i64; 2], end: [i64; 2]) -> Vec<[i64; 2]> {
+    let d_row = (end[0] - start[0]).abs();
+    let d_col = -(end[1] - start[1]).abs();
+    let step_row = if start[0] < end[0] {1} else {-1};
+    let step_col = if start[1] < end[1] {1} else {-1};
+
+    let mut cells: Vec<[i64; 2]> = Vec::with_capacity((d_row - d_col + 1) as usize);
+    let mut cell = start;
+    let mut error = d_row + d_col;
+
+    loop {
+        cells.push(cell);
+        if cell == end {break;}
+
+        let double_error = 2 * error;
+        if double_error >= d_col {
+            error += d_col;
+            cell[0] += step_row;
+        }
+        if double_error <= d_row {
+            error += d_row;
+            cell[1] += step_col;
+        }
+    }
+
+    cells
+}
+
+
+
+/// Return the given path of cells with an added cell at each diagonal step, so consecutive cells are
+/// adjacent horizontally or vertically. The added cell is next to the first cell of the step, on the same row.
+pub fn orthogonal_path(cells: &[[usize; 2]]) -> Vec<[usize; 2]> {
+    let mut path: Vec<[usize; 2]> = Vec::with_capacity(2 * cells.len());
+
+    for (i, &cell) in cells.iter().enumerate() {
+        if i > 0 && cells[i - 1][0] != cell[0] && cells[i - 1][1] != cell[1] {
+            path.push([cells[i - 1][0], cell[1]]);
+        }
+        path.push(cell);
+    }
+
+    path
+}
+
+
+
+/// Return every cell of a grid of the given size whose center is inside the polygon made of the given rings (scanline
+/// algorithm, with the even-odd rule: the inner rings of a multipolygon are holes). Points are (row, column) coordinates;
+/// each ring is closed even if its last point differs from the first one.
+pub fn polygon_cells(rings: &[Vec<[f64; 2]>], rows: usize, columns: usize) -> Vec<[usize; 2]> {
+    let mut cells: Vec<[usize; 2]> = Vec::new();
+    if rings.iter().all(|ring| ring.len() < 3) {return cells;}
+
+    // Only scan the rows crossed by the polygon
+    let points = rings.iter().flatten();
+    let min_row = points.clone().map(|point| point[0]).fold(f64::INFINITY, f64::min).max(0.0);
+    let max_row = points.map(|point| point[0]).fold(f64::NEG_INFINITY, f64::max).min(rows as f64);
+
+    for row in (min_row.floor() as usize)..(max_row.ceil() as usize).min(rows) {
+        let y = row as f64 + 0.5;
+
+        // Columns where the edges of the polygon cross the center of the row
+        let mut crossings: Vec<f64> = Vec::new();
+        for ring in rings {
+            for (i, start) in ring.iter().enumerate() {
+                let end = ring[(i + 1) % ring.len()];
+                if (start[0] <= y) != (end[0] <= y) {
+                    crossings.push(start[1] + (y - start[0]) / (end[0] - start[0]) * (end[1] - start[1]));
+                }
+            }
+        }
+        crossings.sort_by(|a, b| a.total_cmp(b));
+
+        // The cells between 2 consecutive crossings are inside the polygon
+        for pair in crossings.chunks_exact(2) {
+            let first = (pair[0] - 0.5).ceil().max(0.0) as usize;
+            let last = (pair[1] - 0.5).floor().min(columns as f64 - 1.0);
+            if last < 0.0 {continue;}
+
+            for column in first..=(last as usize) {
+                cells.push([row, column]);
+            }
+        }
+    }
+
+    cells
+}
+
+
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    /// Return true if each cell of the path is next to the previous one, horizontally, vertically or diagonally
+    fn is_contiguous(cells: &[[i64; 2]]) -> bool {
+        cells.windows(2).all(|pair| (pair[0][0] - pair[1][0]).abs() <= 1 && (pair[0][1] - pair[1][1]).abs() <= 1 && pair[0] != pair[1])
+    }
+
+
+    fn assert_close(point: [f64; 2], expected: [f64; 2]) {
+        assert!((point[0] - expected[0]).abs() < 1e-9 && (point[1] - expected[1]).abs() < 1e-9, "{:?} != {:?}", point, expected);
+    }
+
+
+
+    #[test]
+    fn outside_segments_are_clipped_out() {
+        // Above the rectangle, beside it, and crossing the corner lines without entering it
+        assert!(clip_segment([-5.0, 2.0], [-1.0, 8.0], 10.0, 20.0).is_none());
+        assert!(clip_segment([2.0, 25.0], [8.0, 30.0], 10.0, 20.0).is_none());
+        assert!(clip_segment([-2.0, 18.0], [2.0, 24.0], 10.0, 20.0).is_none());
+    }
+
+
+
+    #[test]
+    fn crossing_segments_are_clipped_to_the_rectangle() {
+        // A road crossing the whole view, both of its ends outside
+        let (start, end) = clip_segment([5.0, -10.0], [5.0, 30.0], 10.0, 20.0).unwrap();
+        assert_close(start, [5.0, 0.0]);
+        assert_close(end, [5.0, 20.0]);
+
+        // A diagonal from beyond the top left corner to beyond the bottom right one
+        let (start, end) = clip_segment([-5.0, -10.0], [15.0, 30.0], 10.0, 20.0).unwrap();
+        assert_close(start, [0.0, 0.0]);
+        assert_close(end, [10.0, 20.0]);
+
+        // Inside segments are unchanged
+        let (start, end) = clip_segment([1.0, 2.0], [3.0, 4.0], 10.0, 20.0).unwrap();
+        assert_close(start, [1.0, 2.0]);
+        assert_close(end, [3.0, 4.0]);
+    }
+
+
+
+    #[test]
+    fn zero_length_segments_are_one_cell() {
+        let (start, end) = clip_segment([3.5, 4.5], [3.5, 4.5], 10.0, 20.0).unwrap();
+        assert_close(start, [3.5, 4.5]);
+        assert_close(end, [3.5, 4.5]);
+        assert!(clip_segment([-3.5, 4.5], [-3.5, 4.5], 10.0, 20.0).is_none());
+
+        assert_eq!(line_cells([3, 4], [3, 4]), vec![[3, 4]]);
+        assert_eq!(orthogonal_path(&[[3, 4]]), vec![[3, 4]]);
+    }
+
+
+
+    #[test]
+    fn steep_lines_are_contiguous() {
+        for (start, end) in [([0, 0], [17, 3]), ([17, 3], [0, 0]), ([0, 5], [20, 0]), ([2, 2], [9, 9])] {
+            let cells = line_cells(start, end);
+            assert_eq!(cells.first(), Some(&start));
+            assert_eq!(cells.last(), Some(&end));
+            assert!(is_contiguous(&cells));
+
+            // One cell per row of a steep line
+            let rows = (end[0] - start[0]).abs().max((end[1] - start[1]).abs()) + 1;
+            assert_eq!(cells.len() as i64, rows);
+        }
+
+        // Diagonal steps are split in two orthogonal steps
+        let path = orthogonal_path(&[[0, 0], [1, 1], [2, 1], [3, 2]]);
+        assert_eq!(path, vec![[0, 0], [0, 1], [1, 1], [2, 1], [2, 2], [3, 2]]);
+        assert!(path.windows(2).all(|pair| pair[0][0].abs_diff(pair[1][0]) + pair[0][1].abs_diff(pair[1][1]) == 1));
+    }
+
+
+
+    /// Return the cells of the given rings, sorted
+    fn sorted_polygon_cells(rings: &[Vec<[f64; 2]>], rows: usize, columns: usize) -> Vec<[usize; 2]> {
+        let mut cells = polygon_cells(rings, rows, columns);
+        cells.sort();
+        cells
+    }
+
+
+    /// Return every cell of the given rows and columns, sorted
+    fn block(rows: std::ops::Range<usize>, columns: std::ops::Range<usize>) -> Vec<[usize; 2]> {
+        rows.flat_map(|row| columns.clone().map(move |column| [row, column])).collect()
+    }
+
+
+
+    #[test]
+    fn inner_rings_are_holes() {
+        let outer = vec![[0.0, 0.0], [0.0, 6.0], [6.0, 6.0], [6.0, 0.0], [0.0, 0.0]];
+        let inner = vec![[2.0, 2.0], [2.0, 4.0], [4.0, 4.0], [4.0, 2.0], [2.0, 2.0]];
+
+        let cells = sorted_polygon_cells(&[outer.clone(), inner], 10, 10);
+        let mut expected: Vec<[usize; 2]> = block(0..6, 0..6).into_iter().filter(|cell| !block(2..4, 2..4).contains(cell)).collect();
+        expected.sort();
+        assert_eq!(cells, expected);
+
+        // Without its inner ring, the polygon is filled
+        assert_eq!(sorted_polygon_cells(&[outer], 10, 10), block(0..6, 0..6));
+    }
+
+
+
+    #[test]
+    fn polygons_are_clipped_at_the_edges() {
+        // A square overflowing the grid on every side
+        let square = vec![[-5.0, -5.0], [-5.0, 15.0], [15.0, 15.0], [15.0, -5.0], [-5.0, -5.0]];
+        assert_eq!(sorted_polygon_cells(&[square], 4, 6), block(0..4, 0..6));
+
+        // A square overflowing the bottom right corner
+        let corner = vec![[2.0, 3.0], [2.0, 9.0], [9.0, 9.0], [9.0, 3.0], [2.0, 3.0]];
+        assert_eq!(sorted_polygon_cells(&[corner], 4, 6), block(2..4, 3..6));
+
+        // A polygon outside of the grid
+        let outside = vec![[-5.0, -5.0], [-5.0, -1.0], [-1.0, -1.0], [-1.0, -5.0]];
+        assert!(polygon_cells(&[outside], 4, 6).is_empty());
+    }
+
+
+
+    #[test]
+    fn open_rings_are_closed() {
+        let closed = vec![[0.0, 0.0], [0.0, 4.0], [4.0, 4.0], [4.0, 0.0], [0.0, 0.0]];
+        let open = vec![[0.0, 0.0], [0.0, 4.0], [4.0, 4.0], [4.0, 0.0]];
+        assert_eq!(sorted_polygon_cells(&[open], 10, 10), sorted_polygon_cells(&[closed], 10, 10));
+
+        // A triangle whose closing edge is the diagonal
+        let triangle = vec![[0.0, 0.0], [0.0, 4.0], [4.0, 4.0]];
+        let cells = sorted_polygon_cells(&[triangle], 10, 10);
+        assert!(cells.contains(&[0, 3]) && cells.contains(&[3, 3]));
+        assert!(!cells.contains(&[3, 0]));
+
+        // Rings of less than 3 points have no inside
+        assert!(polygon_cells(&[vec![[0.0, 0.0], [4.0, 4.0]]], 10, 10).is_empty());
+    }
+}
\ No newline at end of file
@@ -1,16 +1,30 @@
-const PRIMAY_HIGHWAY: [&str; 4] = ["\x1b[93m═\x1b[0m", "\x1b[93m⇗\x1b[0m", "\x1b[93m║\x1b[0m", "\x1b[93m⇖\x1b[0m"];
-const SECONDARY_HIGHWAY: [&str; 4] = ["═", "⇗", "║", "⇖"];
-const TERTIARY_HIGHWAY: [&str; 4] = ["\x1b[33m-\x1b[0m", "\x1b[33m/\x1b[0m", "\x1b[33m|\x1b[0m", "\x1b[33m\\\x1b[0m"];
-const QUATERNARY_HIGHWAY: [&str; 4] = ["-", "/", "|", "\\"];
-const SMALL_HIGHWAY: [&str; 4] = ["\x1b[90m-\x1b[0m", "\x1b[90m/\x1b[0m", "\x1b[90m|\x1b[0m", "\x1b[90m\\\x1b[0m"];
-const VERY_SMALL_HIGHWAY: [&str; 4] = ["\x1b[32m⋯\x1b[0m", "\x1b[32m⋰\x1b[0m", "\x1b[32m⋮\x1b[0m", "\x1b[32m⋱\x1b[0m"];
+use crate::osm;
+use crate::cell::Cell;
 
-const RIVER: [&str; 4] = ["\x1b[34m═\x1b[0m", "\x1b[34m⇗\x1b[0m", "\x1b[34m║\x1b[0m", "\x1b[34m⇖\x1b[0m"];
-const STREAM: [&str; 4] = ["\x1b[34m-\x1b[0m", "\x1b[34m/\x1b[0m", "\x1b[34m|\x1b[0m", "\x1b[34m\\\x1b[0m"];
+use std::collections::HashMap;
 
 
 
-const WAY_TYPES: [&str; 36] = [
+/// Connections of a cell to its neighbors, combined in a bit mask
+pub const NORTH: u8 = 1;
+pub const EAST: u8 = 2;
+pub const SOUTH: u8 = 4;
+pub const WEST: u8 = 8;
+
+
+
+// Draw priorities: a cell is only drawn over by an element of the same or a higher priority.
+// The priority of a way is WAY_PRIORITY plus its importance (see get_way_priority).
+pub const AREA_PRIORITY: u8 = 10;
+pub const BUILDING_PRIORITY: u8 = 20;
+pub const BOUNDARY_PRIORITY: u8 = 30;
+const WAY_PRIORITY: u8 = 40;
+pub const ROUTE_PRIORITY: u8 = 100;
+pub const LABEL_PRIORITY: u8 = 110;
+
+
+
+const WAY_TYPES: [&str; 41] = [
     "motorway",
     "trunk",
     "primary",
@@ -48,6 +62,12 @@ const WAY_TYPES: [&str; 36] = [
     "ditch",
     "fairway",
     "fish_pass",
+
+    "rail",
+    "light_rail",
+    "subway",
+    "tram",
+    "narrow_gauge",
 ];
 
 
@@ -56,34 +76,165 @@ const WAY_TYPES: [&str; 36] = [
 
 /// Return the index of the given way_type, or none if does not exists
 pub fn get_way_index(value: &str) -> Option<usize> {
-    for i in 0..36 {
-        if WAY_TYPES[i] == value {return Some(i);}
+    WAY_TYPES.iter().position(|&way_type| way_type == value)
+}
+
+
+
+/// Classes of ways drawn the same way (see get_way_class), by decreasing importance
+pub const WAY_CLASSES: [&str; 10] = ["motorway", "primary", "secondary", "minor", "residential", "path", "river", "stream", "rail", "light-rail"];
+
+
+/// Return the class of the given way type index (see WAY_CLASSES), or None if it is not a way type
+pub fn get_way_class(way_type_index: usize) -> Option<usize> {
+    match way_type_index {
+        0 | 1 | 7 | 8 => Some(0),
+        2 | 9 => Some(1),
+        3 | 10 | 16 | 20 => Some(2),
+        4 | 5 | 11 | 13 | 19 => Some(3),
+        6 | 12 => Some(4),
+        14 | 15 | 17 | 18 | 21..=25 => Some(5),
+        26 | 27 => Some(6),
+        28..=35 => Some(7),
+        36 => Some(8),
+        37..=40 => Some(9),
+        _ => Option::None,
     }
-    Option::None
 }
 
 
 
+/// Return the draw priority of the given way type: the more important the way, the higher its priority
+pub fn get_way_priority(way_type_index: usize) -> u8 {
+    WAY_PRIORITY + (WAY_TYPES.len() - way_type_index.min(WAY_TYPES.len())) as u8
+}
+
+
+
+
+
+/// Maximum level of details (every way type is included)
+pub const MAX_DETAIL_LVL: u8 = 6;
+
+
+
+/// A layer of the map: a category of OpenStreetMap elements that can be requested and displayed
+#[derive(clap::ArgEnum, Copy, Clone, PartialEq, Eq)]
+pub enum Layer {
+    Roads,      // Highways, from motorways to footpaths
+    Water,      // Waterways: rivers, canals, streams...
+    Rail,       // Railways: trains, subways, trams...
+    Areas,      // Filled areas: water bodies, forests, parks, landuse (see AREA_TYPES)
+    Boundaries, // Administrative boundaries: country, region and city borders (see ADMIN_LEVELS)
+    Routes,     // Bus lines (see ROUTE_TYPES), only displayed from the level of details ROUTES_DETAIL_LVL
+    Buildings,  // Footprints of the buildings, only displayed from the level of details BUILDINGS_DETAIL_LVL
+    Poi,        // Points of interest (see PoiClass), only displayed from the level of details POI_DETAIL_LVL
+    Places,     // Names of the cities, towns, villages and hamlets (see PLACE_TYPES)
+    StreetNames,// Names of the streets, along the roads, only displayed from the level of details STREET_NAMES_DETAIL_LVL
+}
+
+
+/// Every layer of the map
+pub const ALL_LAYERS: [Layer; 10] = [
+    Layer::Roads, Layer::Water, Layer::Rail, Layer::Areas, Layer::Boundaries, Layer::Routes, Layer::Buildings, Layer::Poi, Layer::Places,
+    Layer::StreetNames,
+];
+
+/// Minimum level of details at which buildings are requested and displayed
+pub const BUILDINGS_DETAIL_LVL: u8 = 5;
+
+/// Minimum level of details at which points of interest are requested and displayed
+pub const POI_DETAIL_LVL: u8 = 5;
+
+/// Minimum level of details at which routes are requested and displayed
+pub const ROUTES_DETAIL_LVL: u8 = 4;
+
+/// Minimum level of details at which the names of the streets are displayed
+pub const STREET_NAMES_DETAIL_LVL: u8 = 5;
+
+/// Values of the "route" tag of the displayed route relations
+pub const ROUTE_TYPES: [&str; 2] = ["bus", "trolleybus"];
+
+
+/// Displayed administrative boundaries: (admin_level, minimum level of details, maximum level of details).
+/// Country borders are not requested when zoomed in, as the relation of a whole country would be downloaded.
+const ADMIN_LEVELS: [(u8, u8, u8); 4] = [
+    (2, 0, 3),
+    (4, 1, 4),
+    (6, 2, 5),
+    (8, 3, 6),
+];
+
+
+/// Values of the "place" tag of the labelled places: (value, rank, minimum level of details).
+/// Places of a lower rank are labelled first.
+pub const PLACE_TYPES: [(&str, usize, u8); 4] = [
+    ("city", 0, 0),
+    ("town", 1, 2),
+    ("village", 2, 4),
+    ("hamlet", 3, 5),
+];
+
+
+/// Tags of the filled areas: (key, value, category of the area (see get_area_index), minimum level of details)
+const AREA_TYPES: [(&str, &str, usize, u8); 23] = [
+    ("natural", "water", 0, 2),
+    ("waterway", "riverbank", 0, 2),    // Older tagging of the surface of the rivers, still in some data
+    ("landuse", "reservoir", 0, 2),
+    ("landuse", "basin", 0, 4),
+    ("natural", "wood", 1, 3),
+    ("landuse", "forest", 1, 3),
+    ("leisure", "park", 2, 4),
+    ("leisure", "garden", 2, 5),
+    ("leisure", "nature_reserve", 2, 3),
+    ("landuse", "meadow", 2, 4),
+    ("landuse", "grass", 2, 5),
+    ("landuse", "recreation_ground", 2, 5),
+    ("landuse", "village_green", 2, 5),
+    ("landuse", "cemetery", 2, 5),
+    ("landuse", "farmland", 3, 4),
+    ("landuse", "orchard", 3, 5),
+    ("landuse", "vineyard", 3, 5),
+    ("landuse", "residential", 4, 5),
+    ("landuse", "commercial", 5, 5),
+    ("landuse", "retail", 5, 5),
+    ("landuse", "industrial", 5, 5),
+    ("landuse", "railway", 5, 5),
+    ("landuse", "construction", 5, 5),
+];
+
+
+
+/// Return the layer the given way type belongs to
+pub fn get_way_layer(way_type: &str) -> Option<Layer> {
+    match get_way_index(way_type) {
+        Some(way_index) if way_index > 35 => Some(Layer::Rail),
+        Some(way_index) if way_index > 25 => Some(Layer::Water),
+        Some(_) => Some(Layer::Roads),
+        None => Option::None,
+    }
+}
+
 
 
 /// Return a Vector of way types (motorway, canal, primary, etc.)
-/// corresponding to the given detail level
-pub fn get_way_types(detail_lvl: u8) -> Vec<&'static str> {
+/// corresponding to the given detail level, in the given layers
+pub fn get_way_types(detail_lvl: u8, layers: &[Layer]) -> Vec<&'static str> {
 
     // Base list of way types.
     let mut res: Vec<&str> = Vec::from(["motorway", "motorway_link", "trunk", "trunk_link", "river"]);
 
     if detail_lvl > 0 {
-        res.append(&mut Vec::from(["primary", "primary_link", "riverbank", "canal"]));
+        res.append(&mut Vec::from(["primary", "primary_link", "riverbank", "canal", "rail"]));
     }
     if detail_lvl > 1 {
         res.append(&mut Vec::from(["secondary", "secondary_link", "stream"]));
     }
     if detail_lvl > 2 {
-        res.append(&mut Vec::from(["tertiary", "tertiary_link"]));
+        res.append(&mut Vec::from(["tertiary", "tertiary_link", "light_rail", "subway"]));
     }
     if detail_lvl > 3 {
-        res.append(&mut Vec::from(["unclassified", "bus_guideway", "busway"]))
+        res.append(&mut Vec::from(["unclassified", "bus_guideway", "busway", "tram", "narrow_gauge"]))
     }
     if detail_lvl > 4 {
         res.append(&mut Vec::from(["residential", "living_street", "service", "road", "pressurised", "drain", "ditch", "fairway"]));
@@ -92,11 +243,248 @@ pub fn get_way_types(detail_lvl: u8) -> Vec<&'static str> {
         res.append(&mut Vec::from(["pedestrian", "track", "escape", "raceway", "footway", "steps", "corridor", "path", "fish_pass"]));
     }
 
+    res.retain(|way_type| get_way_layer(way_type).is_some_and(|layer| layers.contains(&layer)));
     res
 }
 
 
 
+/// Return the (key, value) tags of the filled areas of the given level of details, if the areas are in the given layers
+pub fn get_area_types(detail_lvl: u8, layers: &[Layer]) -> Vec<(&'static str, &'static str)> {
+    if !layers.contains(&Layer::Areas) {return Vec::new();}
+
+    AREA_TYPES.iter().filter(|area_type| detail_lvl >= area_type.3).map(|area_type| (area_type.0, area_type.1)).collect()
+}
+
+
+
+/// Return the admin levels of the administrative boundaries of the given level of details, if the boundaries are in the given layers
+pub fn get_admin_levels(detail_lvl: u8, layers: &[Layer]) -> Vec<u8> {
+    if !layers.contains(&Layer::Boundaries) {return Vec::new();}
+
+    ADMIN_LEVELS.iter().filter(|admin_level| (admin_level.1..=admin_level.2).contains(&detail_lvl)).map(|admin_level| admin_level.0).collect()
+}
+
+
+
+/// Categories of the administrative boundaries (see get_boundary_index), with their description in the legend
+pub const BOUNDARY_CATEGORIES: [(&str, &str); 3] = [("country", "country border"), ("region", "region border"), ("local", "local border")];
+
+
+/// Return the category of an administrative boundary of the given admin level: 0 = country, 1 = region, 2 = local
+pub fn get_boundary_index(admin_level: u8) -> usize {
+    if admin_level <= 2 {0}
+    else if admin_level <= 4 {1}
+    else {2}
+}
+
+
+
+/// Return the description of the boundaries of the given category, as displayed in the legend
+pub fn get_boundary_name(boundary_index: usize) -> &'static str {
+    BOUNDARY_CATEGORIES[boundary_index.min(BOUNDARY_CATEGORIES.len() - 1)].1
+}
+
+
+
+/// Return the values of the "place" tag of the places labelled at the given level of details, if the places are in the given layers
+pub fn get_place_types(detail_lvl: u8, layers: &[Layer]) -> Vec<&'static str> {
+    if !layers.contains(&Layer::Places) {return Vec::new();}
+
+    PLACE_TYPES.iter().filter(|place_type| detail_lvl >= place_type.2).map(|place_type| place_type.0).collect()
+}
+
+
+
+/// Return the rank of a place from the value of its "place" tag (0 = city, 1 = town, 2 = village, 3 = hamlet),
+/// or None if it is not a labelled place
+pub fn get_place_rank(place_type: &str) -> Option<usize> {
+    PLACE_TYPES.iter().find(|entry| entry.0 == place_type).map(|entry| entry.1)
+}
+
+
+
+/// Categories of the filled areas (see get_area_index)
+pub const AREA_CATEGORIES: [&str; 6] = ["water", "forest", "park", "farmland", "residential", "industrial"];
+
+
+/// Return the category of an area with the given tags, or None if it is not a filled area:
+/// 0 = water, 1 = forest, 2 = park and grass, 3 = farmland, 4 = residential, 5 = commercial and industrial
+pub fn get_area_index(tags: &HashMap<String, String>) -> Option<usize> {
+    AREA_TYPES.iter().find(|area_type| tags.get(area_type.0).is_some_and(|value| value == area_type.1)).map(|area_type| area_type.2)
+}
+
+
+
+/// Selection of the OpenStreetMap elements to request and display, for a level of details, some layers
+/// and some classes of points of interest
+#[derive(Clone, PartialEq, Eq)]
+pub struct ElementFilter {
+    pub detail_lvl: u8,
+    pub layers: Vec<Layer>,
+    pub way_types: Vec<&'static str>,   // Types of the kept highways, waterways and railways (see get_way_types)
+    pub area_types: Vec<(&'static str, &'static str)>,  // Tags of the kept filled areas (see get_area_types)
+    pub buildings: bool,                // If true, the buildings are kept
+    pub routes: bool,                   // If true, the route relations of ROUTE_TYPES are kept
+    pub admin_levels: Vec<u8>,          // Admin levels of the kept administrative boundaries (see get_admin_levels)
+    pub place_types: Vec<&'static str>, // Values of the "place" tag of the kept named places (see get_place_types)
+    pub street_names: bool,             // If true, the names of the kept roads are displayed
+    pub poi_classes: Vec<PoiClass>,     // Classes of the kept points of interest
+}
+
+
+impl ElementFilter {
+
+    /// Return the filter of the given level of details, layers and classes of points of interest
+    pub fn new(detail_lvl: u8, layers: &[Layer], poi_classes: &[PoiClass]) -> ElementFilter {
+        let has_poi = detail_lvl >= POI_DETAIL_LVL && layers.contains(&Layer::Poi);
+
+        ElementFilter {
+            detail_lvl,
+            layers: layers.to_vec(),
+            way_types: get_way_types(detail_lvl, layers),
+            area_types: get_area_types(detail_lvl, layers),
+            buildings: detail_lvl >= BUILDINGS_DETAIL_LVL && layers.contains(&Layer::Buildings),
+            routes: detail_lvl >= ROUTES_DETAIL_LVL && layers.contains(&Layer::Routes),
+            admin_levels: get_admin_levels(detail_lvl, layers),
+            place_types: get_place_types(detail_lvl, layers),
+            street_names: detail_lvl >= STREET_NAMES_DETAIL_LVL && layers.contains(&Layer::StreetNames),
+            poi_classes: if has_poi {poi_classes.to_vec()} else {Vec::new()},
+        }
+    }
+
+
+    /// Return true if a way with the given tags is kept
+    pub fn keeps_way(&self, tags: &HashMap<String, String>) -> bool {
+        if (self.buildings && tags.contains_key("building")) || self.keeps_area(tags) {true}
+        else {self.way_types.contains(&osm::way_type(tags))}
+    }
+
+
+    /// Return true if the given relation is kept: an administrative boundary of a kept admin level,
+    /// a multipolygon of a kept filled area, or a displayed route
+    pub fn keeps_relation(&self, relation: &osm::OsmRelation) -> bool {
+        if relation.is_boundary() {relation.admin_level().is_some_and(|admin_level| self.admin_levels.contains(&admin_level))}
+        else if relation.is_multipolygon() {self.keeps_area(&relation.tags)}
+        else if relation.is_route() {self.routes && relation.tags.get("route").is_some_and(|route| ROUTE_TYPES.contains(&route.as_str()))}
+        else {false}
+    }
+
+
+    /// Return true if a filled area with the given tags is kept
+    pub fn keeps_area(&self, tags: &HashMap<String, String>) -> bool {
+        self.area_types.iter().any(|(key, value)| tags.get(*key).is_some_and(|tag_value| tag_value == value))
+    }
+
+
+    /// Return true if a node with the given tags is kept on its own (as a point of interest or a named place),
+    /// and not only as a part of a kept way
+    pub fn keeps_node(&self, tags: &HashMap<String, String>) -> bool {
+        let is_kept_place = tags.contains_key("name") && tags.get("place").is_some_and(|place_type| self.place_types.contains(&place_type.as_str()));
+        is_kept_place || PoiClass::from_tags(tags).is_some_and(|poi_class| self.poi_classes.contains(&poi_class))
+    }
+}
+
+
+
+
+
+
+/// A class of points of interest: nodes with one of the given tags
+#[derive(clap::ArgEnum, Copy, Clone, PartialEq, Eq)]
+pub enum PoiClass {
+    Fuel,
+    Pharmacy,
+    Hospital,
+    Restaurant,
+    Cafe,
+    Station,
+    School,
+    Bank,
+    Parking,
+    PostOffice,
+    Police,
+    Worship,
+}
+
+
+/// Every class of points of interest
+pub const ALL_POI_CLASSES: [PoiClass; 12] = [
+    PoiClass::Fuel, PoiClass::Pharmacy, PoiClass::Hospital, PoiClass::Restaurant, PoiClass::Cafe, PoiClass::Station,
+    PoiClass::School, PoiClass::Bank, PoiClass::Parking, PoiClass::PostOffice, PoiClass::Police, PoiClass::Worship,
+];
+
+
+impl PoiClass {
+
+    /// Return the tag key and the tag values of the nodes of this class
+    pub fn tags(&self) -> (&'static str, &'static [&'static str]) {
+        match self {
+            PoiClass::Fuel => ("amenity", &["fuel"]),
+            PoiClass::Pharmacy => ("amenity", &["pharmacy"]),
+            PoiClass::Hospital => ("amenity", &["hospital", "clinic"]),
+            PoiClass::Restaurant => ("amenity", &["restaurant", "fast_food", "food_court"]),
+            PoiClass::Cafe => ("amenity", &["cafe", "bar", "pub"]),
+            PoiClass::Station => ("railway", &["station", "halt", "tram_stop", "subway_entrance"]),
+            PoiClass::School => ("amenity", &["school", "college", "university", "kindergarten"]),
+            PoiClass::Bank => ("amenity", &["bank", "atm"]),
+            PoiClass::Parking => ("amenity", &["parking"]),
+            PoiClass::PostOffice => ("amenity", &["post_office"]),
+            PoiClass::Police => ("amenity", &["police"]),
+            PoiClass::Worship => ("amenity", &["place_of_worship"]),
+        }
+    }
+
+
+    /// Return the class of a node with the given tags, or None if it is not a point of interest
+    pub fn from_tags(tags: &HashMap<String, String>) -> Option<PoiClass> {
+        ALL_POI_CLASSES.iter().copied().find(|poi_class| {
+            let (key, values) = poi_class.tags();
+            tags.get(key).is_some_and(|value| values.contains(&value.as_str()))
+        })
+    }
+
+
+    /// Return the name of this class, as displayed in the legend of the map
+    pub fn name(&self) -> &'static str {
+        match self {
+            PoiClass::Fuel => "fuel",
+            PoiClass::Pharmacy => "pharmacy",
+            PoiClass::Hospital => "hospital",
+            PoiClass::Restaurant => "restaurant",
+            PoiClass::Cafe => "cafe",
+            PoiClass::Station => "station",
+            PoiClass::School => "school",
+            PoiClass::Bank => "bank",
+            PoiClass::Parking => "parking",
+            PoiClass::PostOffice => "post office",
+            PoiClass::Police => "police",
+            PoiClass::Worship => "worship",
+        }
+    }
+}
+
+
+
+
+
+
+/// Categories of the buildings (see get_building_index)
+pub const BUILDING_CATEGORIES: [&str; 6] = ["other", "residential", "commercial", "industrial", "religious", "public"];
+
+
+/// Return the category of a building from the value of its "building" tag:
+/// 0 = other, 1 = residential, 2 = commercial, 3 = industrial, 4 = religious, 5 = public
+pub fn get_building_index(building_type: &str) -> usize {
+    match building_type {
+        "residential" | "house" | "detached" | "semidetached_house" | "apartments" | "terrace" | "dormitory" | "bungalow" => 1,
+        "commercial" | "retail" | "office" | "supermarket" | "kiosk" | "hotel" => 2,
+        "industrial" | "warehouse" | "factory" | "manufacture" | "hangar" => 3,
+        "church" | "cathedral" | "chapel" | "mosque" | "synagogue" | "temple" | "shrine" | "monastery" | "religious" => 4,
+        "public" | "civic" | "government" | "school" | "university" | "college" | "hospital" | "kindergarten" | "train_station" | "townhall" => 5,
+        _ => 0,
+    }
+}
 
 
 
@@ -105,57 +493,33 @@ fn is_between(n: f64, m1: f64, m2: f64) -> bool {n >= m1 && n <= m2}
 
 
 
-/// Return the string (a character and ansi escape codes, if any) representing a node
-/// of the road_type (ex: motorway, secondary, path...) with the given angle
-pub fn get_road_repr(way_type_index: usize, angle: f64) -> String {
-    
-    // Get the orientation of the character.
-    // Example with a simple line: 0= -, 1= /, 2= |, 3= \
-    let mut orientation: usize = 0;
-    if is_between(angle, 67.5, 112.5) || is_between(angle, 247.5, 292.5) {orientation = 0;}
-    else if is_between(angle, 112.5, 157.5) || is_between(angle, 292.5, 337.5) {orientation = 1;}
-    else if is_between(angle, 337.5, 360.0) || is_between(angle, 0.0, 22.5) {orientation = 2;}
-    else if is_between(angle, 22.5, 67.5) || is_between(angle, 202.5, 247.5) {orientation = 3;}
+/// Return the orientation of a segment of way with the given angle (90 for an horizontal segment, 0 or 180 for a vertical one),
+/// as the index of its character in a set of line characters: 0 = -, 1 = /, 2 = |, 3 = \
+pub fn get_orientation(angle: f64) -> usize {
+    if is_between(angle, 67.5, 112.5) || is_between(angle, 247.5, 292.5) {0}
+    else if is_between(angle, 112.5, 157.5) || is_between(angle, 292.5, 337.5) {1}
+    else if is_between(angle, 157.5, 202.5) || is_between(angle, 337.5, 360.0) || is_between(angle, 0.0, 22.5) {2}
+    else if is_between(angle, 22.5, 67.5) || is_between(angle, 202.5, 247.5) {3}
+    else {0}
+}
 
-    match way_type_index {
-        0 => String::from(PRIMAY_HIGHWAY[orientation]),
-        1 => String::from(PRIMAY_HIGHWAY[orientation]),
-        2 => String::from(SECONDARY_HIGHWAY[orientation]),
-        3 => String::from(TERTIARY_HIGHWAY[orientation]),
-        4 => String::from(QUATERNARY_HIGHWAY[orientation]),
-        5 => String::from(QUATERNARY_HIGHWAY[orientation]),
-        6 => String::from(SMALL_HIGHWAY[orientation]),
-        7 => String::from(PRIMAY_HIGHWAY[orientation]),
-        8 => String::from(PRIMAY_HIGHWAY[orientation]),
-        9 => String::from(SECONDARY_HIGHWAY[orientation]),
-        10 => String::from(TERTIARY_HIGHWAY[orientation]),
-        11 => String::from(QUATERNARY_HIGHWAY[orientation]),
-        12 => String::from(SMALL_HIGHWAY[orientation]),
-        13 => String::from(QUATERNARY_HIGHWAY[orientation]),
-        14 => String::from(VERY_SMALL_HIGHWAY[orientation]),
-        15 => String::from(VERY_SMALL_HIGHWAY[orientation]),
-        16 => String::from(TERTIARY_HIGHWAY[orientation]),
-        17 => String::from(VERY_SMALL_HIGHWAY[orientation]),
-        18 => String::from(VERY_SMALL_HIGHWAY[orientation]),
-        19 => String::from(QUATERNARY_HIGHWAY[orientation]),
-        20 => String::from(TERTIARY_HIGHWAY[orientation]),
-        21 => String::from(VERY_SMALL_HIGHWAY[orientation]),
-        22 => String::from(VERY_SMALL_HIGHWAY[orientation]),
-        23 => String::from(VERY_SMALL_HIGHWAY[orientation]),
-        24 => String::from(VERY_SMALL_HIGHWAY[orientation]),
-        25 => String::from(VERY_SMALL_HIGHWAY[orientation]),
-
-        26 => String::from(RIVER[orientation]),
-        27 => String::from(RIVER[orientation]),
-        28 => String::from(STREAM[orientation]),
-        29 => String::from(STREAM[orientation]),
-        30 => String::from(STREAM[orientation]),
-        31 => String::from(STREAM[orientation]),
-        32 => String::from(STREAM[orientation]),
-        33 => String::from(STREAM[orientation]),
-        34 => String::from(STREAM[orientation]),
-        35 => String::from(STREAM[orientation]),
-
-        _ => String::from(" "),
-    }
+
+
+
+
+/// Set of characters used to draw the ways on the map
+#[derive(clap::ArgEnum, Copy, Clone, PartialEq, Eq)]
+pub enum Charset {
+    Box,        // Box-drawing characters connecting each cell to its neighbors, so junctions and corners are continuous
+    Ascii,      // One character per cell, chosen from the type and the direction of the way
+    Braille,    // Unicode Braille patterns: 2x4 dots per cell, colored by the most important way of the cell
+}
+
+
+
+
+
+/// Return the given cell of a way (see theme::Theme::get_road_repr, theme::Theme::get_box_repr...) dimmed, as the way is in a tunnel
+pub fn get_tunnel_repr(cell: Cell) -> Cell {
+    Cell {dim: true, ..cell}
 }
\ No newline at end of file
@@ -0,0 +1,516 @@
+use crate::cell::{Cell, Color};
+use crate::config;
+use crate::error::Error;
+use crate::style::{self, PoiClass};
+
+use clap::ArgEnum;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::value::{Table, Value};
+use unicode_width::UnicodeWidthChar;
+
+
+
+/// Built-in themes, with their TOML source. Every theme is applied over the default one (see Theme::load).
+const BUILTIN_THEMES: [(&str, &str); 5] = [
+    ("default", include_str!("../themes/default.toml")),
+    ("dark", include_str!("../themes/dark.toml")),
+    ("light", include_str!("../themes/light.toml")),
+    ("monochrome", include_str!("../themes/monochrome.toml")),
+    ("high-contrast", include_str!("../themes/high-contrast.toml")),
+];
+
+/// Name of the directory of the user themes, in the configuration directory
+static THEMES_DIR_NAME: &str = "themes";
+
+/// Maximum number of themes based on each other, to stop at the loops
+const MAX_BASE_DEPTH: usize = 8;
+
+
+
+// Box-drawing characters, indexed by the connections of the cell (see style::NORTH, style::EAST, style::SOUTH and style::WEST).
+// A cell without any connection (a way shorter than a cell) is drawn as a dot.
+// Railways are drawn as lines crossed by sleepers on their straight parts.
+const BOX_SETS: [(&str, [char; 16]); 5] = [
+    ("light", ['·', '╵', '╶', '└', '╷', '│', '┌', '├', '╴', '┘', '─', '┴', '┐', '┤', '┬', '┼']),
+    ("heavy", ['•', '╹', '╺', '┗', '╻', '┃', '┏', '┣', '╸', '┛', '━', '┻', '┓', '┫', '┳', '╋']),
+    ("double", ['•', '║', '═', '╚', '║', '║', '╔', '╠', '═', '╝', '═', '╩', '╗', '╣', '╦', '╬']),
+    ("rail", ['•', '╹', '╺', '┗', '╻', '╂', '┏', '┣', '╸', '┛', '┿', '┻', '┓', '┫', '┳', '╋']),
+    ("light-rail", ['·', '╵', '╶', '└', '╷', '╫', '┌', '├', '╴', '┘', '╪', '┴', '┐', '┤', '┬', '┼']),
+];
+
+
+
+
+
+
+/// Characters and attributes of an element of the map
+#[derive(Clone)]
+struct Style {
+    glyphs: Vec<char>,      // Characters of the element. Their number depends on the element (see Theme::empty)
+    color: Option<Color>,   // None for the default color of the terminal
+    bold: bool,
+    italic: bool,
+}
+
+
+impl Style {
+
+    /// Return a style of the given number of characters, in the default color
+    fn new(glyph_count: usize) -> Style {
+        Style {glyphs: vec![' '; glyph_count], color: Option::None, bold: false, italic: false}
+    }
+
+
+
+    /// Return the cell of the given character in this style, drawn with the given priority
+    fn cell(&self, glyph: char, priority: u8) -> Cell {
+        Cell {bold: self.bold, italic: self.italic, ..Cell::from(glyph, self.color, priority)}
+    }
+
+
+
+    /// Change the attribute of this style of the given key (color, bold or italic) to the given value.
+    /// Return false if the key is not an attribute. The path of the key is used in the error messages.
+    fn set_attribute(&mut self, key: &str, value: &Value, path: &str) -> Result<bool, Error> {
+        match key {
+            "color" => self.color = parse_color(value, path)?,
+            "bold" => self.bold = parse_bool(value, path)?,
+            "italic" => self.italic = parse_bool(value, path)?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+
+
+    /// Change the attributes of this style given in the TOML table, and its characters, given by the key "glyph" (or "glyphs"
+    /// when the number of characters is free). The other attributes are kept.
+    fn apply(&mut self, value: &Value, path: &str, free_glyph_count: bool) -> Result<(), Error> {
+        for (key, value) in as_table(value, path)? {
+            let key_path = format!("{}.{}", path, key);
+            if self.set_attribute(key, value, &key_path)? {continue;}
+
+            match key.as_str() {
+                "glyph" if !free_glyph_count && !self.glyphs.is_empty() => self.glyphs = parse_glyphs(value, &key_path, Some(self.glyphs.len()))?,
+                "glyphs" if free_glyph_count => self.glyphs = parse_glyphs(value, &key_path, Option::None)?,
+                _ => return Err(Error::MalformedConfig(format!("{}: unknown key", key_path))),
+            }
+        }
+        Ok(())
+    }
+}
+
+
+
+/// Characters and attributes of a class of ways
+#[derive(Clone)]
+struct WayStyle {
+    line: Style,            // Characters of the ascii charset, by orientation (see style::get_orientation), and attributes of the way
+    box_set: [char; 16],    // Characters of the box charset, by connections (see BOX_SETS)
+}
+
+
+impl WayStyle {
+
+    /// Change the characters and the attributes of this class of ways given in the TOML table: "line" (4 characters),
+    /// "box" (the name of a set of BOX_SETS, or 16 characters) and the attributes of a style. The other ones are kept.
+    fn apply(&mut self, value: &Value, path: &str) -> Result<(), Error> {
+        for (key, value) in as_table(value, path)? {
+            let key_path = format!("{}.{}", path, key);
+            if self.line.set_attribute(key, value, &key_path)? {continue;}
+
+            match key.as_str() {
+                "line" => self.line.glyphs = parse_glyphs(value, &key_path, Some(4))?,
+                "box" => self.box_set = parse_box_set(value, &key_path)?,
+                _ => return Err(Error::MalformedConfig(format!("{}: unknown key", key_path))),
+            }
+        }
+        Ok(())
+    }
+}
+
+
+
+
+
+
+/// Characters and colors of the elements of the map, loaded from a TOML file (see Theme::load and themes/default.toml)
+#[derive(Clone)]
+pub struct Theme {
+    ways: Vec<WayStyle>,            // By class of ways (see style::WAY_CLASSES)
+    areas: Vec<Style>,              // By category of areas (see style::AREA_CATEGORIES)
+    buildings: Vec<Style>,          // By category of buildings (see style::BUILDING_CATEGORIES)
+    boundaries: Vec<Style>,         // By category of boundaries (see style::BOUNDARY_CATEGORIES)
+    places: Vec<Style>,             // Markers of the places, by rank (see style::get_place_rank). Their names have the same attributes
+    routes: Style,                  // Attributes of the routes, and dot drawn where there is no way under them
+    street_names: Style,            // Attributes of the names of the streets (without any character)
+    street_numbers: Style,          // Numbers of the streets whose name is in the legend
+    points_of_interest: Vec<Style>, // Symbols of the points of interest (single-width characters), by class (see style::ALL_POI_CLASSES)
+}
+
+
+impl Theme {
+
+    /// Return a theme where every element is blank, with the number of characters of each element
+    fn empty() -> Theme {
+        Theme {
+            ways: vec![WayStyle {line: Style::new(4), box_set: [' '; 16]}; style::WAY_CLASSES.len()],
+            areas: vec![Style::new(1); style::AREA_CATEGORIES.len()],
+            buildings: vec![Style::new(1); style::BUILDING_CATEGORIES.len()],
+            boundaries: vec![Style::new(1); style::BOUNDARY_CATEGORIES.len()],
+            places: vec![Style::new(1); style::PLACE_TYPES.len()],
+            routes: Style::new(1),
+            street_names: Style::new(0),
+            street_numbers: Style::new(1),
+            points_of_interest: vec![Style::new(1); style::ALL_POI_CLASSES.len()],
+        }
+    }
+
+
+
+    /// Return the theme of the given name: a built-in theme (see BUILTIN_THEMES), a theme of the configuration directory
+    /// ($XDG_CONFIG_HOME/rustreet/themes/<name>.toml on Linux), or the theme file at the given path, relative to the given directory.
+    /// A theme only gives the characters and attributes it changes from the theme named by its "base" key,
+    /// or from the default theme.
+    pub fn load(name: &str, dir: &Path) -> Result<Theme, Error> {
+        let mut theme = Theme::empty();
+        theme.apply_theme("default", dir, 0)?;
+        if name != "default" {theme.apply_theme(name, dir, 0)?;}
+
+        Ok(theme)
+    }
+
+
+
+    /// Apply the theme of the given name (or path, relative to the given directory) over this one, after the theme
+    /// it is based on. The depth is the number of themes based on this one, to stop at the loops.
+    fn apply_theme(&mut self, name: &str, dir: &Path, depth: usize) -> Result<(), Error> {
+        if depth > MAX_BASE_DEPTH {return Err(Error::MalformedConfig(format!("the theme '{}' is based on itself", name)));}
+
+        let (source, theme_dir) = theme_source(name, dir)?;
+        let table = config::parse_toml(&source, name)?;
+
+        // The path of the base theme is relative to the theme file
+        match table.get("base") {
+            Some(Value::String(base)) if base == "default" => (),
+            Some(Value::String(base)) => self.apply_theme(base, &theme_dir, depth + 1)?,
+            Some(_) => return Err(Error::MalformedConfig(format!("{}: the base theme must be a string", name))),
+            None => (),
+        }
+
+        for (key, value) in &table {
+            let path = format!("{}: {}", name, key);
+
+            match key.as_str() {
+                "base" => (),
+                "ways" => for (way_class, value) in as_table(value, &path)? {
+                    let index = find_key(&style::WAY_CLASSES, way_class, &path)?;
+                    self.ways[index].apply(value, &format!("{}.{}", path, way_class))?;
+                },
+                "areas" => apply_styles(&mut self.areas, &style::AREA_CATEGORIES, value, &path)?,
+                "buildings" => apply_styles(&mut self.buildings, &style::BUILDING_CATEGORIES, value, &path)?,
+                "boundaries" => apply_styles(&mut self.boundaries, &style::BOUNDARY_CATEGORIES.map(|category| category.0), value, &path)?,
+                "places" => apply_styles(&mut self.places, &style::PLACE_TYPES.map(|place_type| place_type.0), value, &path)?,
+                "routes" => self.routes.apply(value, &path, false)?,
+                "street-names" => self.street_names.apply(value, &path, false)?,
+                "street-numbers" => self.street_numbers.apply(value, &path, true)?,
+                "poi" => {
+                    let poi_names = style::ALL_POI_CLASSES.map(|poi_class| poi_class.to_possible_value().map_or("", |value| value.get_name()));
+                    apply_styles(&mut self.points_of_interest, &poi_names, value, &path)?
+                }
+                _ => return Err(Error::MalformedConfig(format!("{}: {}: unknown key", name, key))),
+            }
+        }
+
+        Ok(())
+    }
+
+
+
+    /// Return the style of the given way type, or None if it is not a way type
+    fn way_style(&self, way_type_index: usize) -> Option<&WayStyle> {
+        style::get_way_class(way_type_index).map(|way_class| &self.ways[way_class])
+    }
+
+
+
+    /// Return the cell (a character and its color) representing a node
+    /// of the road_type (ex: motorway, secondary, path...) with the given angle
+    pub fn get_road_repr(&self, way_type_index: usize, angle: f64) -> Cell {
+        match self.way_style(way_type_index) {
+            Some(way_style) => way_style.line.cell(way_style.line.glyphs[style::get_orientation(angle)], style::get_way_priority(way_type_index)),
+            None => Cell::blank(),
+        }
+    }
+
+
+
+    /// Return the cell (a box-drawing character) representing a cell connected to the given neighbors,
+    /// with the line weight and the color of the given way type
+    pub fn get_box_repr(&self, way_type_index: usize, connections: u8) -> Cell {
+        let connections = connections & (style::NORTH | style::EAST | style::SOUTH | style::WEST);
+
+        match self.way_style(way_type_index) {
+            Some(way_style) => way_style.line.cell(way_style.box_set[connections as usize], style::get_way_priority(way_type_index)),
+            None => Cell::blank(),
+        }
+    }
+
+
+
+    /// Return the cell (a Braille pattern) representing a cell with the given raised dots, colored as the given way type.
+    /// Bit i of dots is the dot i + 1 of the Unicode Braille numbering.
+    pub fn get_braille_repr(&self, way_type_index: usize, dots: u8) -> Cell {
+        let pattern = char::from_u32(0x2800 + dots as u32).unwrap_or(' ');
+
+        match self.way_style(way_type_index) {
+            Some(way_style) => way_style.line.cell(pattern, style::get_way_priority(way_type_index)),
+            None => Cell::blank(),
+        }
+    }
+
+
+
+    /// Return the given cell recolored as a route passing through it, and drawn over the ways.
+    /// The character of the cell is kept with the element it represents (the way the route follows),
+    /// or replaced by a dot if the cell is empty.
+    pub fn get_route_repr(&self, cell: Cell) -> Cell {
+        let glyph = if cell.glyph == ' ' {self.routes.glyphs[0]} else {cell.glyph};
+        Cell {feature_ref: cell.feature_ref, ..self.routes.cell(glyph, style::ROUTE_PRIORITY)}
+    }
+
+
+
+    /// Return the cell representing a cell covered by an area of the given category
+    pub fn get_area_repr(&self, area_index: usize) -> Cell {
+        let area_style = &self.areas[area_index.min(self.areas.len() - 1)];
+        area_style.cell(area_style.glyphs[0], style::AREA_PRIORITY)
+    }
+
+
+
+    /// Return the cell representing a cell covered by a building of the given category
+    pub fn get_building_repr(&self, building_index: usize) -> Cell {
+        let building_style = &self.buildings[building_index.min(self.buildings.len() - 1)];
+        building_style.cell(building_style.glyphs[0], style::BUILDING_PRIORITY)
+    }
+
+
+
+    /// Return the cell representing a cell crossed by a boundary of the given category.
+    /// The most important boundaries are drawn over the other ones.
+    pub fn get_boundary_repr(&self, boundary_index: usize) -> Cell {
+        let boundary_index = boundary_index.min(self.boundaries.len() - 1);
+        let priority = style::BOUNDARY_PRIORITY + (self.boundaries.len() - 1 - boundary_index) as u8;
+        self.boundaries[boundary_index].cell(self.boundaries[boundary_index].glyphs[0], priority)
+    }
+
+
+
+    /// Return the cell (a marker) representing a place of the given rank
+    pub fn get_place_marker_repr(&self, rank: usize) -> Cell {
+        let rank = rank.min(self.places.len() - 1);
+        self.get_place_label_repr(rank, self.places[rank].glyphs[0])
+    }
+
+
+
+    /// Return the cell representing a character of the name of a place of the given rank
+    pub fn get_place_label_repr(&self, rank: usize, c: char) -> Cell {
+        self.places[rank.min(self.places.len() - 1)].cell(c, style::LABEL_PRIORITY)
+    }
+
+
+
+    /// Return the cell representing a character of the name of a street
+    pub fn get_street_name_repr(&self, c: char) -> Cell {
+        self.street_names.cell(c, style::LABEL_PRIORITY)
+    }
+
+
+
+    /// Return the maximum number of streets whose name is replaced by a number, when it doesn't fit along the street
+    pub fn street_number_count(&self) -> usize {
+        self.street_numbers.glyphs.len()
+    }
+
+
+
+    /// Return the cell representing the number of a street (from 0) whose name is in the legend
+    pub fn get_street_number_repr(&self, number: usize) -> Cell {
+        let glyphs = &self.street_numbers.glyphs;
+        self.street_numbers.cell(glyphs[number.min(glyphs.len() - 1)], style::LABEL_PRIORITY)
+    }
+
+
+
+    /// Return the cell representing a point of interest of the given class
+    pub fn get_poi_repr(&self, poi_class: PoiClass) -> Cell {
+        let index = style::ALL_POI_CLASSES.iter().position(|class| *class == poi_class).unwrap_or_default();
+        self.points_of_interest[index].cell(self.points_of_interest[index].glyphs[0], style::LABEL_PRIORITY)
+    }
+}
+
+
+
+
+
+
+
+/// Return the TOML source of the theme of the given name: a built-in theme, a theme of the configuration directory,
+/// or a theme file at the given path, relative to the given directory. Also return the directory of the theme file,
+/// against which the path of its base theme is resolved
+fn theme_source(name: &str, dir: &Path) -> Result<(String, PathBuf), Error> {
+    if let Some((_, source)) = BUILTIN_THEMES.iter().find(|(theme_name, _)| *theme_name == name) {
+        return Ok((source.to_string(), dir.to_path_buf()));
+    }
+
+    let user_theme = config::config_dir().map(|config_dir| config_dir.join(THEMES_DIR_NAME).join(format!("{}.toml", name)));
+    let path = match user_theme {
+        Some(path) if path.is_file() => path,
+        _ => dir.join(name),
+    };
+
+    if !path.is_file() {
+        let builtin_names: Vec<&str> = BUILTIN_THEMES.iter().map(|(theme_name, _)| *theme_name).collect();
+        return Err(Error::MalformedConfig(format!("no theme named '{}' (the built-in themes are {})", name, builtin_names.join(", "))));
+    }
+
+    let theme_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    Ok((fs::read_to_string(path)?, theme_dir))
+}
+
+
+
+/// Apply the TOML table of styles to the styles of the given names, with one character each
+fn apply_styles(styles: &mut [Style], names: &[&str], value: &Value, path: &str) -> Result<(), Error> {
+    for (name, value) in as_table(value, path)? {
+        let index = find_key(names, name, path)?;
+        styles[index].apply(value, &format!("{}.{}", path, name), false)?;
+    }
+    Ok(())
+}
+
+
+
+/// Return the index of the given key in the given names
+fn find_key(names: &[&str], key: &str, path: &str) -> Result<usize, Error> {
+    names.iter().position(|name| *name == key)
+        .ok_or_else(|| Error::MalformedConfig(format!("{}.{}: unknown key (expected one of {})", path, key, names.join(", "))))
+}
+
+
+
+/// Return the given TOML value as a table
+fn as_table<'a>(value: &'a Value, path: &str) -> Result<&'a Table, Error> {
+    value.as_table().ok_or_else(|| Error::MalformedConfig(format!("{} must be a table", path)))
+}
+
+
+
+/// Return the given TOML value as a boolean
+fn parse_bool(value: &Value, path: &str) -> Result<bool, Error> {
+    value.as_bool().ok_or_else(|| Error::MalformedConfig(format!("{} must be true or false", path)))
+}
+
+
+
+/// Return the color of the given name, or None for "default" (the default color of the terminal)
+fn parse_color(value: &Value, path: &str) -> Result<Option<Color>, Error> {
+    match value.as_str() {
+        Some("default") => Ok(Option::None),
+        Some(name) => Color::from_name(name).map(Some).ok_or_else(|| Error::MalformedConfig(format!("{}: unknown color '{}'", path, name))),
+        None => Err(Error::MalformedConfig(format!("{} must be the name of a color", path))),
+    }
+}
+
+
+
+/// Return the characters of the given string, which must have the given number of characters (if any), or at least one.
+/// Each character takes one cell of the map, so it must be one column wide on the terminal.
+fn parse_glyphs(value: &Value, path: &str, glyph_count: Option<usize>) -> Result<Vec<char>, Error> {
+    let glyphs: Vec<char> = value.as_str().ok_or_else(|| Error::MalformedConfig(format!("{} must be a string", path)))?.chars().collect();
+
+    if let Some(glyph) = glyphs.iter().find(|glyph| glyph.width() != Some(1)) {
+        return Err(Error::MalformedConfig(format!("{}: '{}' is not a single-width character", path, glyph)));
+    }
+
+    match glyph_count {
+        Some(count) if glyphs.len() != count => Err(Error::MalformedConfig(format!("{} must have {} character(s)", path, count))),
+        None if glyphs.is_empty() => Err(Error::MalformedConfig(format!("{} must have at least one character", path))),
+        _ => Ok(glyphs),
+    }
+}
+
+
+
+/// Return the box-drawing characters of the given set name (see BOX_SETS) or the given 16 characters
+fn parse_box_set(value: &Value, path: &str) -> Result<[char; 16], Error> {
+    if let Some((_, box_set)) = BOX_SETS.iter().find(|(set_name, _)| Some(*set_name) == value.as_str()) {
+        return Ok(*box_set);
+    }
+
+    let glyphs = match value.as_str() {
+        Some(glyphs) if glyphs.chars().count() == 16 => parse_glyphs(value, path, Some(16))?,
+        _ => {
+            let set_names: Vec<&str> = BOX_SETS.iter().map(|(set_name, _)| *set_name).collect();
+            return Err(Error::MalformedConfig(format!("{} must be one of {}, or 16 box-drawing characters", path, set_names.join(", "))));
+        }
+    };
+
+    let mut box_set = [' '; 16];
+    box_set.copy_from_slice(&glyphs);
+    Ok(box_set)
+}
+
+
+
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    /// Return the error of loading a theme of the given TOML source, or None if it is valid
+    fn load_error(file_name: &str, source: &str) -> Option<String> {
+        let path = std::env::temp_dir().join(format!("rustreet-{}-{}", std::process::id(), file_name));
+        fs::write(&path, source).unwrap();
+        let result = Theme::load(path.to_str().unwrap(), Path::new(""));
+        fs::remove_file(&path).unwrap();
+
+        match result {
+            Ok(_) => Option::None,
+            Err(Error::MalformedConfig(message)) => Some(message),
+            Err(_) => panic!("unexpected error"),
+        }
+    }
+
+
+
+    #[test]
+    fn builtin_themes_are_valid() {
+        for (name, _) in BUILTIN_THEMES {
+            assert!(Theme::load(name, Path::new("")).is_ok(), "{}", name);
+        }
+    }
+
+
+
+    #[test]
+    fn wide_glyphs_are_rejected() {
+        assert_eq!(load_error("narrow.toml", "[poi]\nfuel = { glyph = \"F\" }"), Option::None);
+
+        let message = load_error("emoji.toml", "[poi]\nfuel = { glyph = \"⛽\" }").unwrap();
+        assert!(message.ends_with("poi.fuel.glyph: '⛽' is not a single-width character"), "{}", message);
+
+        let message = load_error("cjk.toml", "[street-numbers]\nglyphs = \"一二三\"").unwrap();
+        assert!(message.contains("'一'"), "{}", message);
+
+        let message = load_error("combining.toml", "[places]\ncity = { glyph = \"\u{301}\" }").unwrap();
+        assert!(message.contains("places.city.glyph"), "{}", message);
+    }
+}
\ No newline at end of file